@@ -19,11 +19,16 @@
 //! so we need to maintain the count of write and read reference.
 //! When page fault occurs, if the read reference count is 0 and the write reference count is 1，
 //! The copy process should be skipped and the entry is mark as writable directly.
+//!
+//! Page tables sharing frames with each other, like those of a forked process and its parent,
+//! must share the reference counts too: wrap each with CowExt::with_rc_map() and the same map.
 
 use super::paging::*;
 use super::*;
 use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use core::ops::{Deref, DerefMut};
+use spin::Mutex;
 
 /// Wrapper for page table, supporting shared map & copy-on-write
 pub struct CowExt<T: PageTable> {
@@ -38,10 +43,16 @@ impl<T: PageTable> CowExt<T> {
      **  @retval CowExt               the COW extension created
      */
     pub fn new(page_table: T) -> Self {
-        CowExt {
-            page_table,
-            rc_map: FrameRcMap::default(),
-        }
+        Self::with_rc_map(page_table, FrameRcMap::default())
+    }
+    /*
+     **  @brief  create a COW extension sharing the reference counts with others
+     **  @param  page_table: T        the inner page table
+     **  @param  rc_map: FrameRcMap   the reference counts of the shared frames
+     **  @retval CowExt               the COW extension created
+     */
+    pub fn with_rc_map(page_table: T, rc_map: FrameRcMap) -> Self {
+        CowExt { page_table, rc_map }
     }
     /*
      **  @brief  map the virtual address to a target physics address as shared
@@ -78,27 +89,95 @@ impl<T: PageTable> CowExt<T> {
         }
         self.page_table.unmap(addr);
     }
+    /*
+     **  @brief  map the page of a virtual address to the same frame in another page table,
+     **          both copied on write from then on
+     **  @param  addr: VirtAddr       the virual address of the page to share
+     **  @param  other: &mut dyn PageTable
+     **                               the page table to map the page in,
+     **                               it must use the same reference counts
+     **  @retval bool                 whether the page is shared,
+     **                               false if the entries can't be marked shared
+     */
+    pub fn share_with(&mut self, addr: VirtAddr, other: &mut dyn PageTable) -> bool {
+        let entry = self.page_table.get_entry(addr).expect("entry not exist");
+        let frame = entry.target() / PAGE_SIZE;
+        if !entry.readonly_shared() && !entry.writable_shared() {
+            entry.set_shared(true);
+            if !entry.writable_shared() {
+                // no bits for it in the entries of this architecture
+                return false;
+            }
+            entry.set_writable(false);
+            entry.update();
+            self.rc_map.write_increase(&frame);
+        }
+        let writable = entry.writable_shared();
+        let (user, execute, mmio) = (entry.user(), entry.execute(), entry.mmio());
+        let new_entry = other.map(addr, entry.target());
+        new_entry.set_user(user);
+        new_entry.set_execute(execute);
+        new_entry.set_mmio(mmio);
+        new_entry.set_writable(false);
+        new_entry.set_shared(writable);
+        new_entry.update();
+        match writable {
+            true => self.rc_map.write_increase(&frame),
+            false => self.rc_map.read_increase(&frame),
+        }
+        true
+    }
+    /*
+     **  @brief  stop sharing the page of a virtual address before it is unmapped
+     **  @param  addr: VirtAddr       the virual address of the page
+     **  @retval bool                 whether the page is unmapped already,
+     **                               as its frame is still used by other pages.
+     **                               Otherwise the page is not shared anymore,
+     **                               and the frame can be freed with it
+     */
+    pub fn unshare(&mut self, addr: VirtAddr) -> bool {
+        let entry = match self.page_table.get_entry(addr) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if !entry.readonly_shared() && !entry.writable_shared() {
+            return false;
+        }
+        let frame = entry.target() / PAGE_SIZE;
+        if self.rc_map.read_count(&frame) + self.rc_map.write_count(&frame) > 1 {
+            self.unmap_shared(addr);
+            return true;
+        }
+        match entry.writable_shared() {
+            true => self.rc_map.write_decrease(&frame),
+            false => self.rc_map.read_decrease(&frame),
+        }
+        entry.clear_shared();
+        entry.update();
+        false
+    }
     /*
      **  @brief  execute the COW process for page fault
      **          This function must be called whenever PageFault happens.
      **  @param  addr: VirtAddr       the virual address of the page fault
-     **  @param  alloc_frame: impl FnOnce() -> PhysAddr
+     **  @param  alloc_frame: impl FnOnce() -> Option<PhysAddr>
      **                               the page allocation function
      **                               that allocate a page and returns physics address
-     **                               of beginning of the page
+     **                               of beginning of the page, None if out of memory
      **  @retval bool                 whether copy-on-write happens.
      */
     pub fn page_fault_handler(
         &mut self,
         addr: VirtAddr,
-        alloc_frame: impl FnOnce() -> PhysAddr,
+        alloc_frame: impl FnOnce() -> Option<PhysAddr>,
     ) -> bool {
+        let addr = addr & !(PAGE_SIZE - 1);
         let entry = self.page_table.get_entry(addr);
         if entry.is_none() {
             return false;
         }
         let entry = entry.unwrap();
-        if !entry.readonly_shared() && !entry.writable_shared() {
+        if !entry.writable_shared() {
             return false;
         }
         let frame = entry.target() / PAGE_SIZE;
@@ -109,15 +188,26 @@ impl<T: PageTable> CowExt<T> {
             self.rc_map.write_decrease(&frame);
             return true;
         }
+        let target = match alloc_frame() {
+            Some(target) => target,
+            None => return false,
+        };
+        let (user, execute, mmio) = (entry.user(), entry.execute(), entry.mmio());
         use core::mem::MaybeUninit;
         let mut temp_data: [u8; PAGE_SIZE] = unsafe { MaybeUninit::zeroed().assume_init() };
         temp_data[..].copy_from_slice(self.get_page_slice_mut(addr));
 
         self.unmap_shared(addr);
-        self.map(addr, alloc_frame());
+        let entry = self.map(addr, target);
+        entry.set_user(user);
+        entry.set_execute(execute);
+        entry.set_mmio(mmio);
+        entry.set_writable(true);
+        entry.update();
 
         self.get_page_slice_mut(addr)
             .copy_from_slice(&temp_data[..]);
+        self.flush_cache_copy_user(addr, addr + PAGE_SIZE, execute);
         true
     }
 }
@@ -138,9 +228,9 @@ impl<T: PageTable> DerefMut for CowExt<T> {
 
 /// A map contains reference count for shared frame
 ///
-/// It will lazily construct the `BTreeMap`, to avoid heap alloc when heap is unavailable.
-#[derive(Default)]
-struct FrameRcMap(Option<BTreeMap<Frame, (u16, u16)>>);
+/// Clones share the counts, for page tables sharing frames with each other.
+#[derive(Default, Clone)]
+pub struct FrameRcMap(Arc<Mutex<BTreeMap<Frame, (u16, u16)>>>);
 
 type Frame = usize;
 
@@ -150,61 +240,64 @@ impl FrameRcMap {
      **  @param  frame: &Frame        the frame to get the read reference count
      **  @retval u16                  the read reference count
      */
-    fn read_count(&mut self, frame: &Frame) -> u16 {
-        self.map().get(frame).unwrap_or(&(0, 0)).0
+    fn read_count(&self, frame: &Frame) -> u16 {
+        self.0.lock().get(frame).unwrap_or(&(0, 0)).0
     }
     /*
      **  @brief  get the write reference count of the frame
      **  @param  frame: &Frame        the frame to get the write reference count
      **  @retval u16                  the write reference count
      */
-    fn write_count(&mut self, frame: &Frame) -> u16 {
-        self.map().get(frame).unwrap_or(&(0, 0)).1
+    fn write_count(&self, frame: &Frame) -> u16 {
+        self.0.lock().get(frame).unwrap_or(&(0, 0)).1
     }
     /*
      **  @brief  increase the read reference count of the frame
      **  @param  frame: &Frame        the frame to increase the read reference count
      **  @retval none
      */
-    fn read_increase(&mut self, frame: &Frame) {
-        let (r, w) = self.map().get(&frame).unwrap_or(&(0, 0)).clone();
-        self.map().insert(frame.clone(), (r + 1, w));
+    fn read_increase(&self, frame: &Frame) {
+        self.0.lock().entry(*frame).or_insert((0, 0)).0 += 1;
     }
     /*
      **  @brief  decrease the read reference count of the frame
      **  @param  frame: &Frame        the frame to decrease the read reference count
      **  @retval none
      */
-    fn read_decrease(&mut self, frame: &Frame) {
-        self.map().get_mut(frame).unwrap().0 -= 1;
+    fn read_decrease(&self, frame: &Frame) {
+        self.update(frame, |(r, _)| *r -= 1);
     }
     /*
      **  @brief  increase the write reference count of the frame
      **  @param  frame: &Frame        the frame to increase the write reference count
      **  @retval none
      */
-    fn write_increase(&mut self, frame: &Frame) {
-        let (r, w) = self.map().get(&frame).unwrap_or(&(0, 0)).clone();
-        self.map().insert(frame.clone(), (r, w + 1));
+    fn write_increase(&self, frame: &Frame) {
+        self.0.lock().entry(*frame).or_insert((0, 0)).1 += 1;
     }
     /*
      **  @brief  decrease the write reference count of the frame
      **  @param  frame: &Frame        the frame to decrease the write reference count
      **  @retval none
      */
-    fn write_decrease(&mut self, frame: &Frame) {
-        self.map().get_mut(frame).unwrap().1 -= 1;
+    fn write_decrease(&self, frame: &Frame) {
+        self.update(frame, |(_, w)| *w -= 1);
     }
     /*
-     **  @brief  get the internal btree map, lazily initialize the btree map if it is not present
-     **  @retval &mut BTreeMap<Frame, (u16, u16)>
-     **                               the internal btree map
+     **  @brief  change the reference counts of the frame,
+     **          forget the frame once it is not shared anymore
+     **  @param  frame: &Frame        the frame to change the reference counts
+     **  @param  f: impl FnOnce(&mut (u16, u16))
+     **                               the change of the counts
+     **  @retval none
      */
-    fn map(&mut self) -> &mut BTreeMap<Frame, (u16, u16)> {
-        if self.0.is_none() {
-            self.0 = Some(BTreeMap::new());
+    fn update(&self, frame: &Frame, f: impl FnOnce(&mut (u16, u16))) {
+        let mut map = self.0.lock();
+        let counts = map.get_mut(frame).unwrap();
+        f(counts);
+        if *counts == (0, 0) {
+            map.remove(frame);
         }
-        self.0.as_mut().unwrap()
     }
 }
 
@@ -228,7 +321,7 @@ pub mod test {
 
         pt.page_table
             .set_handler(Box::new(move |_, addr: VirtAddr| {
-                pt0.page_fault_handler(addr, || alloc.alloc());
+                pt0.page_fault_handler(addr, || Some(alloc.alloc()));
             }));
 
         test_with(&mut pt);
@@ -11,13 +11,23 @@ impl<T: FrameAllocator> MemoryHandler for ByFrame<T> {
     }
 
     fn map(&self, pt: &mut dyn PageTable, addr: VirtAddr, attr: &MemoryAttr) {
+        if attr.lazy {
+            self.delay().map(pt, addr, attr);
+            return;
+        }
         let target = self.allocator.alloc().expect("failed to allocate frame");
         let entry = pt.map(addr, target);
         attr.apply(entry);
     }
 
     fn unmap(&self, pt: &mut dyn PageTable, addr: VirtAddr) {
-        let target = pt.get_entry(addr).expect("fail to get entry").target();
+        let entry = pt.get_entry(addr).expect("fail to get entry");
+        if !entry.present() {
+            // never touched in a lazy area
+            self.delay().unmap(pt, addr);
+            return;
+        }
+        let target = entry.target();
         self.allocator.dealloc(target);
         pt.unmap(addr);
     }
@@ -29,13 +39,29 @@ impl<T: FrameAllocator> MemoryHandler for ByFrame<T> {
         addr: VirtAddr,
         attr: &MemoryAttr,
     ) {
-        self.map(pt, addr, attr);
+        let entry = src_pt.get_entry(addr).expect("fail to get entry");
+        if !entry.present() {
+            self.delay().map(pt, addr, attr);
+            return;
+        }
+        let target = self.allocator.alloc().expect("failed to allocate frame");
+        let entry = pt.map(addr, target);
+        attr.apply(entry);
         let data = src_pt.get_page_slice_mut(addr);
         pt.get_page_slice_mut(addr).copy_from_slice(data);
     }
 
-    fn handle_page_fault(&self, _pt: &mut dyn PageTable, _addr: VirtAddr) -> bool {
-        false
+    fn handle_page_fault(&self, pt: &mut dyn PageTable, addr: VirtAddr) -> bool {
+        // only pages of a lazy area are not present
+        self.delay().handle_page_fault(pt, addr)
+    }
+
+    fn copy_on_write(&self) -> bool {
+        true
+    }
+
+    fn alloc_frame(&self) -> Option<PhysAddr> {
+        self.allocator.alloc()
     }
 }
 
@@ -43,4 +69,9 @@ impl<T: FrameAllocator> ByFrame<T> {
     pub fn new(allocator: T) -> Self {
         ByFrame { allocator }
     }
+
+    /// Pages of lazy areas are handled like delayed ones
+    fn delay(&self) -> Delay<T> {
+        Delay::new(self.allocator.clone())
+    }
 }
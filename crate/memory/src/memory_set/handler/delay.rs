@@ -55,7 +55,11 @@ impl<T: FrameAllocator> MemoryHandler for Delay<T> {
             // not a delay case
             return false;
         }
-        let frame = self.allocator.alloc().expect("failed to alloc frame");
+        // out of memory: let the faulting process die instead of the kernel
+        let frame = match self.allocator.alloc() {
+            Some(frame) => frame,
+            None => return false,
+        };
         entry.set_target(frame);
         entry.set_present(true);
         entry.update();
@@ -68,6 +72,14 @@ impl<T: FrameAllocator> MemoryHandler for Delay<T> {
         pt.flush_cache_copy_user(addr, addr + len, false);
         true
     }
+
+    fn copy_on_write(&self) -> bool {
+        true
+    }
+
+    fn alloc_frame(&self) -> Option<PhysAddr> {
+        self.allocator.alloc()
+    }
 }
 
 impl<T: FrameAllocator> Delay<T> {
@@ -74,6 +74,14 @@ impl<F: Read, T: FrameAllocator> MemoryHandler for File<F, T> {
         pt.flush_cache_copy_user(addr, addr + read_size, execute);
         true
     }
+
+    fn copy_on_write(&self) -> bool {
+        true
+    }
+
+    fn alloc_frame(&self) -> Option<PhysAddr> {
+        self.allocator.alloc()
+    }
 }
 
 impl<F: Read, T: FrameAllocator> File<F, T> {
@@ -23,6 +23,17 @@ pub trait MemoryHandler: Debug + Send + Sync + 'static {
     /// Handle page fault on `addr`
     /// Return true if success, false if error
     fn handle_page_fault(&self, pt: &mut dyn PageTable, addr: VirtAddr) -> bool;
+
+    /// Whether present pages can be shared copy-on-write on clone, instead of `clone_map`.
+    /// Only for pages private to the memory set, whose frames this handler frees on unmap
+    fn copy_on_write(&self) -> bool {
+        false
+    }
+
+    /// Allocate a frame for the copy of a page written after being shared copy-on-write
+    fn alloc_frame(&self) -> Option<PhysAddr> {
+        None
+    }
 }
 
 impl Clone for Box<dyn MemoryHandler> {
@@ -35,6 +46,12 @@ pub trait FrameAllocator: Debug + Clone + Send + Sync + 'static {
     fn alloc(&self) -> Option<PhysAddr>;
     fn alloc_contiguous(&self, size: usize, align_log2: usize) -> Option<PhysAddr>;
     fn dealloc(&self, target: PhysAddr);
+    /// Free `size` frames starting at `target`, from `alloc_contiguous`
+    fn dealloc_contiguous(&self, target: PhysAddr, size: usize) {
+        for i in 0..size {
+            self.dealloc(target + i * PAGE_SIZE);
+        }
+    }
 }
 
 mod byframe;
@@ -49,3 +66,70 @@ pub use self::delay::Delay;
 pub use self::file::{File, Read};
 pub use self::linear::Linear;
 pub use self::shared::{Shared, SharedGuard};
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use alloc::sync::Arc;
+    use spin::Mutex;
+
+    /// Frame allocator over a small bitmap, allocating runs like `BitAlloc`
+    #[derive(Debug, Clone)]
+    pub(crate) struct MockFrameAlloc(Arc<Mutex<Vec<bool>>>);
+
+    impl MockFrameAlloc {
+        pub(crate) fn new(frames: usize) -> Self {
+            MockFrameAlloc(Arc::new(Mutex::new(vec![false; frames])))
+        }
+        pub(crate) fn used(&self) -> usize {
+            self.0.lock().iter().filter(|&&used| used).count()
+        }
+    }
+
+    impl FrameAllocator for MockFrameAlloc {
+        fn alloc(&self) -> Option<PhysAddr> {
+            self.alloc_contiguous(1, 0)
+        }
+        fn alloc_contiguous(&self, size: usize, align_log2: usize) -> Option<PhysAddr> {
+            let mut frames = self.0.lock();
+            let start = (0..frames.len())
+                .step_by(1 << align_log2)
+                .take_while(|&start| start + size <= frames.len())
+                .find(|&start| frames[start..start + size].iter().all(|&used| !used))?;
+            for used in frames[start..start + size].iter_mut() {
+                *used = true;
+            }
+            Some(start * PAGE_SIZE)
+        }
+        fn dealloc(&self, target: PhysAddr) {
+            let mut frames = self.0.lock();
+            assert!(frames[target / PAGE_SIZE], "double free");
+            frames[target / PAGE_SIZE] = false;
+        }
+    }
+
+    #[test]
+    fn contiguous_stress() {
+        // 4 MiB of frames, room for two 2 MiB blocks
+        let allocator = MockFrameAlloc::new(1024);
+        const BLOCK: usize = 0x200000 / PAGE_SIZE;
+        for _ in 0..100 {
+            let single = allocator.alloc().unwrap();
+            let block = allocator.alloc_contiguous(BLOCK, 9).unwrap();
+            assert_eq!(block % 0x200000, 0);
+            assert!(single < block || single >= block + 0x200000);
+            assert_eq!(allocator.used(), BLOCK + 1);
+            allocator.dealloc_contiguous(block, BLOCK);
+            allocator.dealloc(single);
+            assert_eq!(allocator.used(), 0);
+        }
+
+        // one frame in each half leaves no aligned 2 MiB block
+        let first = allocator.alloc().unwrap();
+        let second = allocator.alloc_contiguous(1, 9).unwrap();
+        assert!(allocator.alloc_contiguous(BLOCK, 9).is_none());
+        allocator.dealloc(first);
+        allocator.dealloc(second);
+        assert!(allocator.alloc_contiguous(BLOCK, 9).is_some());
+    }
+}
@@ -4,6 +4,7 @@ use alloc::{boxed::Box, vec::Vec};
 use core::fmt::{Debug, Error, Formatter};
 use core::mem::size_of;
 
+use crate::cow::{CowExt, FrameRcMap};
 use crate::paging::*;
 
 use super::*;
@@ -30,6 +31,9 @@ impl MemoryArea {
     /// Check the array is within the readable memory.
     /// Return the size of space covered in the area.
     fn check_read_array<S>(&self, ptr: *const S, count: usize) -> usize {
+        if self.attr.inaccessible {
+            return 0;
+        }
         // page align
         let min_bound = (ptr as usize).max(Page::of_addr(self.start_addr).start_address());
         let max_bound = unsafe { ptr.add(count) as usize }
@@ -57,16 +61,32 @@ impl MemoryArea {
         let p3 = Page::of_addr(end_addr - 1) + 1;
         !(p1 <= p2 || p0 >= p3)
     }
+    /// Get the start and end address of the area
+    pub fn range(&self) -> (VirtAddr, VirtAddr) {
+        (self.start_addr, self.end_addr)
+    }
+    /// Get the attributes of the area
+    pub fn attr(&self) -> &MemoryAttr {
+        &self.attr
+    }
+    /// Get the name given when the area was pushed
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
     /// Map all pages in the area to page table `pt`
     fn map(&self, pt: &mut dyn PageTable) {
         for page in Page::range_of(self.start_addr, self.end_addr) {
             self.handler.map(pt, page.start_address(), &self.attr);
         }
     }
-    /// Unmap all pages in the area from page table `pt`
-    fn unmap(&self, pt: &mut dyn PageTable) {
+    /// Unmap all pages in the area from page table `pt`.
+    /// Pages still shared copy-on-write with other page tables keep their frames
+    fn unmap(&self, pt: &mut dyn PageTable, rc_map: &FrameRcMap) {
+        let mut pt = CowExt::with_rc_map(pt, rc_map.clone());
         for page in Page::range_of(self.start_addr, self.end_addr) {
-            self.handler.unmap(pt, page.start_address());
+            if !pt.unshare(page.start_address()) {
+                self.handler.unmap(&mut **pt, page.start_address());
+            }
         }
     }
 }
@@ -78,6 +98,10 @@ pub struct MemoryAttr {
     readonly: bool,
     execute: bool,
     mmio: u8,
+    /// No access from user mode, though the pages stay mapped
+    inaccessible: bool,
+    /// Frames are allocated on the first access, for handlers mapping eagerly otherwise
+    lazy: bool,
 }
 
 impl MemoryAttr {
@@ -101,11 +125,30 @@ impl MemoryAttr {
         self.mmio = value;
         self
     }
+    pub fn inaccessible(mut self) -> Self {
+        self.inaccessible = true;
+        self
+    }
+    pub fn lazy(mut self) -> Self {
+        self.lazy = true;
+        self
+    }
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+    pub fn is_execute(&self) -> bool {
+        self.execute
+    }
+    pub fn is_inaccessible(&self) -> bool {
+        self.inaccessible
+    }
     /// Apply the attributes to page table entry, then update it.
+    /// Pages shared copy-on-write stay read-only until copied.
     /// NOTE: You may need to set present manually.
     pub fn apply(&self, entry: &mut dyn Entry) {
-        entry.set_user(self.user);
-        entry.set_writable(!self.readonly);
+        let shared = entry.writable_shared() || entry.readonly_shared();
+        entry.set_user(self.user && !self.inaccessible);
+        entry.set_writable(!self.readonly && !shared);
         entry.set_execute(self.execute);
         entry.set_mmio(self.mmio);
         entry.update();
@@ -119,6 +162,8 @@ impl MemoryAttr {
 pub struct MemorySet<T: PageTableExt> {
     areas: Vec<MemoryArea>,
     page_table: T,
+    /// Reference counts of the frames shared copy-on-write with clones
+    rc_map: FrameRcMap,
 }
 
 impl<T: PageTableExt> MemorySet<T> {
@@ -127,6 +172,7 @@ impl<T: PageTableExt> MemorySet<T> {
         MemorySet {
             areas: Vec::new(),
             page_table: T::new(),
+            rc_map: FrameRcMap::default(),
         }
     }
     /// Create a new `MemorySet` for kernel remap
@@ -134,6 +180,7 @@ impl<T: PageTableExt> MemorySet<T> {
         MemorySet {
             areas: Vec::new(),
             page_table: T::new_bare(),
+            rc_map: FrameRcMap::default(),
         }
     }
     /// Check the pointer is within the readable memory
@@ -234,7 +281,7 @@ impl<T: PageTableExt> MemorySet<T> {
         for i in 0..self.areas.len() {
             if self.areas[i].start_addr == start_addr && self.areas[i].end_addr == end_addr {
                 let area = self.areas.remove(i);
-                area.unmap(&mut self.page_table);
+                area.unmap(&mut self.page_table, &self.rc_map);
                 return;
             }
         }
@@ -251,7 +298,7 @@ impl<T: PageTableExt> MemorySet<T> {
                 if self.areas[i].start_addr >= start_addr && self.areas[i].end_addr <= end_addr {
                     // subset
                     let area = self.areas.remove(i);
-                    area.unmap(&mut self.page_table);
+                    area.unmap(&mut self.page_table, &self.rc_map);
                     i = i.wrapping_sub(1);
                 } else if self.areas[i].start_addr >= start_addr
                     && self.areas[i].start_addr < end_addr
@@ -265,7 +312,7 @@ impl<T: PageTableExt> MemorySet<T> {
                         handler: area.handler.box_clone(),
                         name: area.name,
                     };
-                    dead_area.unmap(&mut self.page_table);
+                    dead_area.unmap(&mut self.page_table, &self.rc_map);
                     let new_area = MemoryArea {
                         start_addr: end_addr,
                         end_addr: area.end_addr,
@@ -285,7 +332,7 @@ impl<T: PageTableExt> MemorySet<T> {
                         handler: area.handler.box_clone(),
                         name: area.name,
                     };
-                    dead_area.unmap(&mut self.page_table);
+                    dead_area.unmap(&mut self.page_table, &self.rc_map);
                     let new_area = MemoryArea {
                         start_addr: area.start_addr,
                         end_addr: start_addr,
@@ -304,7 +351,7 @@ impl<T: PageTableExt> MemorySet<T> {
                         handler: area.handler.box_clone(),
                         name: area.name,
                     };
-                    dead_area.unmap(&mut self.page_table);
+                    dead_area.unmap(&mut self.page_table, &self.rc_map);
                     let new_area_left = MemoryArea {
                         start_addr: area.start_addr,
                         end_addr: start_addr,
@@ -328,6 +375,79 @@ impl<T: PageTableExt> MemorySet<T> {
         }
     }
 
+    /// Change the attribute of the area `[start_addr, end_addr)`
+    /// and split existed ones when necessary.
+    /// The whole range must be covered by existed areas.
+    pub fn protect(
+        &mut self,
+        start_addr: VirtAddr,
+        end_addr: VirtAddr,
+        attr: MemoryAttr,
+    ) -> VMResult<()> {
+        assert!(start_addr <= end_addr, "invalid memory area");
+        // areas are ordered by start address
+        let mut covered = start_addr;
+        for area in self.areas.iter() {
+            if covered >= end_addr {
+                break;
+            }
+            if area.contains(covered) {
+                covered = area.end_addr;
+            }
+        }
+        if covered < end_addr {
+            return Err(VMError::InvalidPtr);
+        }
+        let mut i = 0;
+        while i < self.areas.len() {
+            if !self.areas[i].is_overlap_with(start_addr, end_addr) {
+                i += 1;
+                continue;
+            }
+            let area = self.areas.remove(i);
+            let start = area.start_addr.max(start_addr);
+            let end = area.end_addr.min(end_addr);
+            if area.start_addr < start {
+                let left_area = MemoryArea {
+                    start_addr: area.start_addr,
+                    end_addr: start,
+                    attr: area.attr,
+                    handler: area.handler.box_clone(),
+                    name: area.name,
+                };
+                self.areas.insert(i, left_area);
+                i += 1;
+            }
+            // update the entries, present or not
+            for page in Page::range_of(start, end) {
+                if let Some(entry) = self.page_table.get_entry(page.start_address()) {
+                    attr.apply(entry);
+                }
+            }
+            let new_area = MemoryArea {
+                start_addr: start,
+                end_addr: end,
+                attr,
+                handler: area.handler.box_clone(),
+                name: area.name,
+            };
+            self.areas.insert(i, new_area);
+            i += 1;
+            if end < area.end_addr {
+                let right_area = MemoryArea {
+                    start_addr: end,
+                    end_addr: area.end_addr,
+                    attr: area.attr,
+                    handler: area.handler,
+                    name: area.name,
+                };
+                self.areas.insert(i, right_area);
+                i += 1;
+            }
+        }
+        Ok(())
+    }
+
     /// Get iterator of areas
     pub fn iter(&self) -> impl Iterator<Item = &MemoryArea> {
         self.areas.iter()
@@ -352,14 +472,68 @@ impl<T: PageTableExt> MemorySet<T> {
         let Self {
             ref mut page_table,
             ref mut areas,
-            ..
+            ref rc_map,
         } = self;
         for area in areas.iter() {
-            area.unmap(page_table);
+            area.unmap(page_table, rc_map);
         }
         areas.clear();
     }
 
+    /// Replace all areas with those of `other`, keeping the page table of `self`.
+    /// The entries of `other` are moved over, so its frames are reused as they are.
+    /// NOTE: `other` must not share pages with a clone of it.
+    pub fn replace_with(&mut self, mut other: Self) {
+        self.clear();
+        for area in other.areas.iter() {
+            for page in Page::range_of(area.start_addr, area.end_addr) {
+                let addr = page.start_address();
+                let entry = other
+                    .page_table
+                    .get_entry(addr)
+                    .expect("failed to get entry");
+                debug_assert!(!entry.writable_shared() && !entry.readonly_shared());
+                let (target, present, writable) =
+                    (entry.target(), entry.present(), entry.writable());
+                let entry = self.page_table.map(addr, target);
+                area.attr.apply(entry);
+                entry.set_writable(writable);
+                entry.set_present(present);
+                entry.update();
+            }
+        }
+        // the frames now belong to `self`, `other` only frees its page table
+        self.areas = core::mem::take(&mut other.areas);
+    }
+
+    /// Write `data` at `addr` through the page table, which doesn't need to be active.
+    /// Pages are faulted in or copied on write first, as a user write would do.
+    pub fn write_bytes(&mut self, addr: VirtAddr, data: &[u8]) -> VMResult<()> {
+        let mut written = 0;
+        while written < data.len() {
+            let addr = addr + written;
+            match self.areas.iter().find(|area| area.contains(addr)) {
+                Some(area) if !area.attr.readonly && !area.attr.inaccessible => {}
+                _ => return Err(VMError::InvalidPtr),
+            }
+            loop {
+                let entry = self.page_table.get_entry(addr).ok_or(VMError::InvalidPtr)?;
+                if entry.present() && entry.writable() {
+                    break;
+                }
+                if !self.handle_page_fault(addr) {
+                    return Err(VMError::InvalidPtr);
+                }
+            }
+            let offset = addr % PAGE_SIZE;
+            let len = (PAGE_SIZE - offset).min(data.len() - written);
+            self.page_table.get_page_slice_mut(addr)[offset..offset + len]
+                .copy_from_slice(&data[written..written + len]);
+            written += len;
+        }
+        Ok(())
+    }
+
     /// Get physical address of the page of given virtual `addr`
     pub fn translate(&mut self, addr: VirtAddr) -> Option<PhysAddr> {
         self.page_table.get_entry(addr).and_then(|entry| {
@@ -377,33 +551,54 @@ impl<T: PageTableExt> MemorySet<T> {
     }
 
     pub fn handle_page_fault(&mut self, addr: VirtAddr) -> bool {
-        let area = self.areas.iter().find(|area| area.contains(addr));
-        match area {
-            Some(area) => area.handler.handle_page_fault(&mut self.page_table, addr),
-            None => false,
+        let area = match self.areas.iter().find(|area| area.contains(addr)) {
+            Some(area) => area,
+            None => return false,
+        };
+        if area.attr.inaccessible {
+            return false;
         }
+        // copy a page shared by clone on write, unless the area is read-only
+        if !area.attr.readonly && area.handler.copy_on_write() {
+            let mut pt = CowExt::with_rc_map(&mut self.page_table, self.rc_map.clone());
+            if pt.page_fault_handler(addr, || area.handler.alloc_frame()) {
+                return true;
+            }
+        }
+        area.handler.handle_page_fault(&mut self.page_table, addr)
     }
 
+    /// Clone the areas into a new page table.
+    /// Present private pages are shared copy-on-write by both, if the page table can mark them.
     pub fn clone(&mut self) -> Self {
         let mut new_page_table = T::new();
         let Self {
             ref mut page_table,
             ref areas,
-            ..
+            ref rc_map,
         } = self;
+        let mut pt = CowExt::with_rc_map(page_table, rc_map.clone());
         for area in areas.iter() {
             for page in Page::range_of(area.start_addr, area.end_addr) {
-                area.handler.clone_map(
-                    &mut new_page_table,
-                    page_table,
-                    page.start_address(),
-                    &area.attr,
-                );
+                let addr = page.start_address();
+                let present = match pt.get_entry(addr) {
+                    Some(entry) => entry.present(),
+                    None => false,
+                };
+                if area.handler.copy_on_write()
+                    && present
+                    && pt.share_with(addr, &mut new_page_table)
+                {
+                    continue;
+                }
+                area.handler
+                    .clone_map(&mut new_page_table, &mut **pt, addr, &area.attr);
             }
         }
         MemorySet {
             areas: areas.clone(),
             page_table: new_page_table,
+            rc_map: rc_map.clone(),
         }
     }
 }
@@ -419,3 +614,285 @@ impl<T: PageTableExt> Debug for MemorySet<T> {
         f.debug_list().entries(self.areas.iter()).finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::handler::test::MockFrameAlloc;
+    use super::handler::{ByFrame, Delay};
+    use super::*;
+
+    type MemorySet = super::MemorySet<MockPageTable>;
+
+    /// Read at `addr` like the CPU does, faulting until the page is present
+    fn read(ms: &mut MemorySet, addr: VirtAddr) -> u8 {
+        while !ms.page_table.get_entry(addr).unwrap().present() {
+            assert!(ms.handle_page_fault(addr), "segmentation fault");
+        }
+        ms.page_table.read(addr)
+    }
+
+    /// Write at `addr` like the CPU does, faulting until the page is writable
+    fn write(ms: &mut MemorySet, addr: VirtAddr, data: u8) {
+        loop {
+            let entry = ms.page_table.get_entry(addr).unwrap();
+            if entry.present() && entry.writable() {
+                break;
+            }
+            assert!(ms.handle_page_fault(addr), "segmentation fault");
+        }
+        ms.page_table.write(addr, data);
+    }
+
+    #[test]
+    fn clone_copy_on_write() {
+        let allocator = MockFrameAlloc::new(16);
+        let mut parent = MemorySet::new();
+        let attr = MemoryAttr::default().user();
+        parent.push(
+            0x1000,
+            0x3000,
+            attr,
+            ByFrame::new(allocator.clone()),
+            "data",
+        );
+        write(&mut parent, 0x1000, 1);
+        write(&mut parent, 0x2000, 2);
+        assert_eq!(allocator.used(), 2);
+
+        // nothing is copied until written
+        let mut child = parent.clone();
+        assert_eq!(allocator.used(), 2);
+        assert_eq!(read(&mut child, 0x1000), 1);
+        assert_eq!(allocator.used(), 2);
+
+        write(&mut child, 0x1000, 3);
+        assert_eq!(allocator.used(), 3);
+        assert_eq!(read(&mut parent, 0x1000), 1);
+        assert_eq!(read(&mut child, 0x1000), 3);
+
+        // the last one sharing a frame writes to it in place
+        write(&mut parent, 0x1000, 4);
+        assert_eq!(allocator.used(), 3);
+        assert_eq!(read(&mut child, 0x1000), 3);
+
+        write(&mut parent, 0x2000, 5);
+        assert_eq!(allocator.used(), 4);
+        assert_eq!(read(&mut child, 0x2000), 2);
+
+        drop(child);
+        assert_eq!(allocator.used(), 2);
+        assert_eq!(read(&mut parent, 0x1000), 4);
+        assert_eq!(read(&mut parent, 0x2000), 5);
+        drop(parent);
+        assert_eq!(allocator.used(), 0);
+    }
+
+    #[test]
+    fn clone_read_only() {
+        let allocator = MockFrameAlloc::new(16);
+        let mut parent = MemorySet::new();
+        let attr = MemoryAttr::default().user().readonly();
+        parent.push(
+            0x1000,
+            0x2000,
+            attr,
+            ByFrame::new(allocator.clone()),
+            "text",
+        );
+        let mut child = parent.clone();
+        assert_eq!(allocator.used(), 1);
+
+        // shared read-only pages are not copied on write
+        assert!(!child.handle_page_fault(0x1000));
+        assert!(!parent.handle_page_fault(0x1000));
+
+        // the frame is freed with its last mapping
+        drop(parent);
+        assert_eq!(allocator.used(), 1);
+        drop(child);
+        assert_eq!(allocator.used(), 0);
+    }
+
+    #[test]
+    fn protect_split() {
+        let allocator = MockFrameAlloc::new(16);
+        let mut ms = MemorySet::new();
+        let attr = MemoryAttr::default().user();
+        ms.push(
+            0x1000,
+            0x4000,
+            attr,
+            ByFrame::new(allocator.clone()),
+            "data",
+        );
+        write(&mut ms, 0x2000, 1);
+        assert!(ms.protect(0x2000, 0x3000, attr.readonly()).is_ok());
+        let ranges: Vec<_> = ms.iter().map(|area| area.range()).collect();
+        assert_eq!(
+            ranges,
+            [(0x1000, 0x2000), (0x2000, 0x3000), (0x3000, 0x4000)]
+        );
+        assert!(!ms.page_table.get_entry(0x2000).unwrap().writable());
+        assert!(!ms.handle_page_fault(0x2000));
+        assert_eq!(read(&mut ms, 0x2000), 1);
+        // the rest is left writable
+        write(&mut ms, 0x1000, 2);
+        write(&mut ms, 0x3000, 3);
+
+        assert!(ms.protect(0x2000, 0x3000, attr).is_ok());
+        write(&mut ms, 0x2000, 4);
+        assert_eq!(allocator.used(), 3);
+    }
+
+    #[test]
+    fn protect_unmapped() {
+        let allocator = MockFrameAlloc::new(16);
+        let mut ms = MemorySet::new();
+        let attr = MemoryAttr::default().user();
+        ms.push(
+            0x1000,
+            0x2000,
+            attr,
+            ByFrame::new(allocator.clone()),
+            "data",
+        );
+        ms.push(
+            0x3000,
+            0x4000,
+            attr,
+            ByFrame::new(allocator.clone()),
+            "data",
+        );
+        // nothing is changed if there is a hole
+        assert!(ms.protect(0x1000, 0x4000, attr.readonly()).is_err());
+        assert_eq!(ms.iter().count(), 2);
+        assert!(ms.iter().all(|area| !area.attr().is_readonly()));
+        assert!(ms.page_table.get_entry(0x1000).unwrap().writable());
+        assert!(ms.page_table.get_entry(0x3000).unwrap().writable());
+    }
+
+    #[test]
+    fn protect_inaccessible() {
+        let allocator = MockFrameAlloc::new(16);
+        let mut ms = MemorySet::new();
+        let attr = MemoryAttr::default().user();
+        ms.push(
+            0x1000,
+            0x2000,
+            attr,
+            ByFrame::new(allocator.clone()),
+            "guard",
+        );
+        write(&mut ms, 0x1000, 1);
+        assert!(ms.protect(0x1000, 0x2000, attr.inaccessible()).is_ok());
+        assert!(!ms.page_table.get_entry(0x1000).unwrap().user());
+        assert!(!ms.handle_page_fault(0x1000));
+        assert!(unsafe { ms.check_read_ptr(0x1000 as *const u8) }.is_err());
+
+        // the frame is kept with its content
+        assert_eq!(allocator.used(), 1);
+        assert!(ms.protect(0x1000, 0x2000, attr).is_ok());
+        assert!(ms.page_table.get_entry(0x1000).unwrap().user());
+        assert_eq!(read(&mut ms, 0x1000), 1);
+    }
+
+    #[test]
+    fn lazy_area() {
+        let allocator = MockFrameAlloc::new(16);
+        let mut parent = MemorySet::new();
+        let attr = MemoryAttr::default().user().lazy();
+        parent.push(
+            0x1000,
+            0x1000 + 256 * PAGE_SIZE,
+            attr,
+            ByFrame::new(allocator.clone()),
+            "lazy",
+        );
+        assert_eq!(allocator.used(), 0);
+        write(&mut parent, 0x80000, 1);
+        assert_eq!(allocator.used(), 1);
+        assert_eq!(read(&mut parent, 0x80000), 1);
+        assert_eq!(allocator.used(), 1);
+
+        // untouched pages stay lazy in a clone
+        let mut child = parent.clone();
+        assert_eq!(allocator.used(), 1);
+        assert_eq!(read(&mut child, 0x2000), 0);
+        assert_eq!(allocator.used(), 2);
+        drop(parent);
+        drop(child);
+        assert_eq!(allocator.used(), 0);
+    }
+
+    #[test]
+    fn write_bytes_to_clone() {
+        let allocator = MockFrameAlloc::new(16);
+        let mut parent = MemorySet::new();
+        let attr = MemoryAttr::default().user();
+        parent.push(
+            0x1000,
+            0x3000,
+            attr,
+            ByFrame::new(allocator.clone()),
+            "data",
+        );
+        parent.push(
+            0x3000,
+            0x4000,
+            attr.readonly(),
+            ByFrame::new(allocator.clone()),
+            "text",
+        );
+        write(&mut parent, 0x1fff, 1);
+        write(&mut parent, 0x2000, 2);
+        let mut child = parent.clone();
+
+        // across a page boundary, only the child sees it
+        assert!(child.write_bytes(0x1fff, &[3, 4]).is_ok());
+        assert_eq!(read(&mut child, 0x1fff), 3);
+        assert_eq!(read(&mut child, 0x2000), 4);
+        assert_eq!(read(&mut parent, 0x1fff), 1);
+        assert_eq!(read(&mut parent, 0x2000), 2);
+
+        assert!(child.write_bytes(0x3000, &[5]).is_err());
+        assert!(child.write_bytes(0x4000, &[5]).is_err());
+    }
+
+    #[test]
+    fn replace_with_moves_pages() {
+        let allocator = MockFrameAlloc::new(16);
+        let mut ms = MemorySet::new();
+        let attr = MemoryAttr::default().user();
+        ms.push(0x1000, 0x3000, attr, ByFrame::new(allocator.clone()), "old");
+        write(&mut ms, 0x1000, 1);
+
+        let mut other = MemorySet::new();
+        other.push(
+            0x4000,
+            0x6000,
+            attr,
+            ByFrame::new(allocator.clone()),
+            "data",
+        );
+        other.push(0x6000, 0x8000, attr, Delay::new(allocator.clone()), "heap");
+        write(&mut other, 0x4000, 2);
+        write(&mut other, 0x5000, 3);
+        assert_eq!(allocator.used(), 4);
+
+        // the old frames are freed and the new ones are not copied
+        ms.replace_with(other);
+        assert_eq!(allocator.used(), 2);
+        let names: Vec<_> = ms.iter().map(|area| area.name()).collect();
+        assert_eq!(names, ["data", "heap"]);
+        assert!(!ms.page_table.get_entry(0x1000).unwrap().present());
+        assert_eq!(read(&mut ms, 0x4000), 2);
+        assert_eq!(read(&mut ms, 0x5000), 3);
+
+        // delayed pages are still allocated on fault
+        assert!(!ms.page_table.get_entry(0x6000).unwrap().present());
+        write(&mut ms, 0x6000, 4);
+        assert_eq!(allocator.used(), 3);
+        drop(ms);
+        assert_eq!(allocator.used(), 0);
+    }
+}
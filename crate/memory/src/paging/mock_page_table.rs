@@ -5,14 +5,23 @@
 
 use super::*;
 use alloc::boxed::Box;
+use core::cell::UnsafeCell;
 
-const PAGE_COUNT: usize = 16;
+const PAGE_COUNT: usize = 1024;
+const FRAME_COUNT: usize = 16;
 const PAGE_SIZE: usize = 4096;
 
+type Memory = [u8; PAGE_SIZE * FRAME_COUNT];
+
+std::thread_local! {
+    /// Physical memory, shared by the mock page tables of a test
+    static MEMORY: UnsafeCell<Memory> = UnsafeCell::new([0; PAGE_SIZE * FRAME_COUNT]);
+}
+
 // a mock page table for test purpose
 pub struct MockPageTable {
-    entries: [MockEntry; PAGE_COUNT],
-    data: [u8; PAGE_SIZE * PAGE_COUNT],
+    entries: Vec<MockEntry>,
+    data: &'static mut Memory,
     page_fault_handler: Option<PageFaultHandler>,
 }
 
@@ -27,6 +36,9 @@ pub struct MockEntry {
     writable_shared: bool,
     readonly_shared: bool,
     swapped: bool,
+    user: bool,
+    execute: bool,
+    mmio: u8,
 }
 
 impl Entry for MockEntry {
@@ -82,22 +94,22 @@ impl Entry for MockEntry {
         self.swapped = value;
     }
     fn user(&self) -> bool {
-        unimplemented!()
+        self.user
     }
-    fn set_user(&mut self, _value: bool) {
-        unimplemented!()
+    fn set_user(&mut self, value: bool) {
+        self.user = value;
     }
     fn execute(&self) -> bool {
-        unimplemented!()
+        self.execute
     }
-    fn set_execute(&mut self, _value: bool) {
-        unimplemented!()
+    fn set_execute(&mut self, value: bool) {
+        self.execute = value;
     }
     fn mmio(&self) -> u8 {
-        unimplemented!()
+        self.mmio
     }
-    fn set_mmio(&mut self, _value: u8) {
-        unimplemented!()
+    fn set_mmio(&mut self, value: u8) {
+        self.mmio = value;
     }
 }
 
@@ -109,6 +121,7 @@ impl PageTable for MockPageTable {
     fn map(&mut self, addr: VirtAddr, target: PhysAddr) -> &mut dyn Entry {
         let entry = &mut self.entries[addr / PAGE_SIZE];
         assert!(!entry.present);
+        *entry = MockEntry::default();
         entry.present = true;
         entry.writable = true;
         entry.target = target & !(PAGE_SIZE - 1);
@@ -125,9 +138,10 @@ impl PageTable for MockPageTable {
     fn get_page_slice_mut<'a, 'b>(&'a mut self, addr: VirtAddr) -> &'b mut [u8] {
         self._read(addr);
         let pa = self.translate(addr) & !(PAGE_SIZE - 1);
-        let data = unsafe { &mut *(&mut self.data as *mut [u8; PAGE_SIZE * PAGE_COUNT]) };
+        let data = unsafe { &mut *(&mut *self.data as *mut Memory) };
         &mut data[pa..pa + PAGE_SIZE]
     }
+    fn flush_cache_copy_user(&mut self, _start: VirtAddr, _end: VirtAddr, _execute: bool) {}
     fn read(&mut self, addr: usize) -> u8 {
         self._read(addr);
         self.data[self.translate(addr)]
@@ -144,10 +158,9 @@ impl MockPageTable {
      **  @retval MockPageTable        the mock page table created
      */
     pub fn new() -> Self {
-        use core::mem::MaybeUninit;
         MockPageTable {
-            entries: [MockEntry::default(); PAGE_COUNT],
-            data: unsafe { MaybeUninit::zeroed().assume_init() },
+            entries: vec![MockEntry::default(); PAGE_COUNT],
+            data: MEMORY.with(|memory| unsafe { &mut *memory.get() }),
             page_fault_handler: None,
         }
     }
@@ -214,6 +227,21 @@ impl MockPageTable {
     }
 }
 
+impl PageTableExt for MockPageTable {
+    fn new_bare() -> Self {
+        MockPageTable::new()
+    }
+    fn map_kernel(&mut self) {}
+    fn token(&self) -> usize {
+        0
+    }
+    unsafe fn set_token(_token: usize) {}
+    fn active_token() -> usize {
+        0
+    }
+    fn flush_tlb() {}
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -42,6 +42,30 @@ pub trait PageTable {
     }
 }
 
+impl<T: PageTable + ?Sized> PageTable for &mut T {
+    fn map(&mut self, addr: VirtAddr, target: PhysAddr) -> &mut dyn Entry {
+        (**self).map(addr, target)
+    }
+    fn unmap(&mut self, addr: VirtAddr) {
+        (**self).unmap(addr)
+    }
+    fn get_entry(&mut self, addr: VirtAddr) -> Option<&mut dyn Entry> {
+        (**self).get_entry(addr)
+    }
+    fn get_page_slice_mut<'a>(&mut self, addr: VirtAddr) -> &'a mut [u8] {
+        (**self).get_page_slice_mut(addr)
+    }
+    fn flush_cache_copy_user(&mut self, start: VirtAddr, end: VirtAddr, execute: bool) {
+        (**self).flush_cache_copy_user(start, end, execute)
+    }
+    fn read(&mut self, addr: VirtAddr) -> u8 {
+        (**self).read(addr)
+    }
+    fn write(&mut self, addr: VirtAddr, data: u8) {
+        (**self).write(addr, data)
+    }
+}
+
 /// Page Table Entry
 pub trait Entry {
     /// Make all changes take effect.
@@ -2,7 +2,9 @@
 
 use super::paging::MMIOType;
 use crate::consts::{KERNEL_OFFSET, MEMORY_OFFSET};
-use crate::memory::{init_heap, kernel_offset, Linear, MemoryAttr, MemorySet, FRAME_ALLOCATOR};
+use crate::memory::{
+    init_heap, kernel_offset, Linear, MemoryAttr, MemorySet, FRAMES_TOTAL, FRAME_ALLOCATOR,
+};
 use crate::sync::SpinNoIrqLock as Mutex;
 use aarch64::paging::frame::PhysFrame as Frame;
 use aarch64::regs::*;
@@ -29,13 +31,17 @@ pub fn init_other() {
 fn init_frame_allocator() {
     use bitmap_allocator::BitAlloc;
     use core::ops::Range;
+    use core::sync::atomic::Ordering;
 
     let end = super::board::probe_memory()
         .expect("failed to find memory map")
         .1;
     let start = kernel_offset(_end as usize) + MEMORY_OFFSET + PAGE_SIZE;
     let mut ba = FRAME_ALLOCATOR.lock();
-    ba.insert(to_range(start, end));
+    let range = to_range(start, end);
+    info!("FrameAllocator: {} frames managed", range.len());
+    FRAMES_TOTAL.store(range.len(), Ordering::Relaxed);
+    ba.insert(range);
     info!("FrameAllocator init end");
 
     /// Transform memory area `[start, end)` to integer range for `FrameAllocator`
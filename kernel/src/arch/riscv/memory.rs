@@ -1,5 +1,5 @@
 use crate::consts::{KERNEL_OFFSET, MEMORY_END, MEMORY_OFFSET};
-use crate::memory::{init_heap, MemorySet, FRAME_ALLOCATOR};
+use crate::memory::{init_heap, MemorySet, FRAMES_TOTAL, FRAME_ALLOCATOR};
 use core::mem;
 use log::*;
 use rcore_memory::PAGE_SIZE;
@@ -29,12 +29,15 @@ pub fn init_other() {
 fn init_frame_allocator() {
     use bitmap_allocator::BitAlloc;
     use core::ops::Range;
+    use core::sync::atomic::Ordering;
 
     let mut ba = FRAME_ALLOCATOR.lock();
     let range = to_range(
         (end as usize) - KERNEL_OFFSET + MEMORY_OFFSET + PAGE_SIZE,
         MEMORY_END,
     );
+    info!("frame allocator: {} frames managed", range.len());
+    FRAMES_TOTAL.store(range.len(), Ordering::Relaxed);
     ba.insert(range);
 
     info!("frame allocator: init end");
@@ -52,6 +52,8 @@ pub fn init() {
             // enable fpu
             cr0.remove(Cr0Flags::EMULATE_COPROCESSOR);
             cr0.insert(Cr0Flags::MONITOR_COPROCESSOR);
+            // the kernel writing to user pages shared copy-on-write must fault too
+            cr0.insert(Cr0Flags::WRITE_PROTECT);
         });
     }
 }
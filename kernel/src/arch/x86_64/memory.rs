@@ -1,6 +1,7 @@
 use super::paging::PageTableImpl;
-use crate::memory::FRAME_ALLOCATOR;
+use crate::memory::{FrameAlloc, FRAMES_TOTAL, FRAME_ALLOCATOR};
 use bitmap_allocator::BitAlloc;
+use core::sync::atomic::Ordering;
 use rboot::{BootInfo, MemoryType};
 use rcore_memory::paging::*;
 use rcore_memory::PAGE_SIZE;
@@ -18,13 +19,27 @@ pub fn init(boot_info: &BootInfo) {
 /// Init FrameAllocator and insert all 'Usable' regions from BootInfo.
 fn init_frame_allocator(boot_info: &BootInfo) {
     let mut ba = FRAME_ALLOCATOR.lock();
+    let mut total = 0;
     for region in boot_info.memory_map.clone().iter {
         if region.ty == MemoryType::CONVENTIONAL {
             let start_frame = region.phys_start as usize / PAGE_SIZE;
             let end_frame = start_frame + region.page_count as usize;
-            ba.insert(start_frame..end_frame);
+            // frames beyond the capacity of the bitmap can't be managed
+            let end_frame = end_frame.min(FrameAlloc::CAP);
+            if end_frame < start_frame + region.page_count as usize {
+                warn!(
+                    "memory: ignoring frames from {:#x} on",
+                    end_frame.max(start_frame) * PAGE_SIZE
+                );
+            }
+            if start_frame < end_frame {
+                ba.insert(start_frame..end_frame);
+                total += end_frame - start_frame;
+            }
         }
     }
+    info!("memory: {} frames managed", total);
+    FRAMES_TOTAL.store(total, Ordering::Relaxed);
 }
 
 /// The method for initializing kernel virtual memory space, a memory space of 512 GiB.
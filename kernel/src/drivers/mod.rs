@@ -2,6 +2,7 @@ use crate::sync::Condvar;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use rcore_fs::dev::{self, BlockDevice, DevError};
 use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv4Address};
@@ -88,9 +89,16 @@ lazy_static! {
 
 pub struct BlockDriverWrapper(pub Arc<dyn BlockDriver>);
 
+/// Blocks read from the device, i.e. misses of the block cache above it
+pub static BLOCK_READS: AtomicUsize = AtomicUsize::new(0);
+
+/// Blocks written to the device
+pub static BLOCK_WRITES: AtomicUsize = AtomicUsize::new(0);
+
 impl BlockDevice for BlockDriverWrapper {
     const BLOCK_SIZE_LOG2: u8 = 9; // 512
     fn read_at(&self, block_id: usize, buf: &mut [u8]) -> dev::Result<()> {
+        BLOCK_READS.fetch_add(1, Ordering::Relaxed);
         match self.0.read_block(block_id, buf) {
             true => Ok(()),
             false => Err(DevError),
@@ -98,6 +106,7 @@ impl BlockDevice for BlockDriverWrapper {
     }
 
     fn write_at(&self, block_id: usize, buf: &[u8]) -> dev::Result<()> {
+        BLOCK_WRITES.fetch_add(1, Ordering::Relaxed);
         match self.0.write_block(block_id, buf) {
             true => Ok(()),
             false => Err(DevError),
@@ -1,5 +1,7 @@
 pub use crate::arch::paging::PageTableImpl;
-use crate::memory::{alloc_frame_contiguous, dealloc_frame, phys_to_virt, virt_to_phys};
+use crate::memory::{
+    alloc_frame_contiguous, dealloc_frame_contiguous, phys_to_virt, virt_to_phys,
+};
 use isomorphic_drivers::provider;
 use rcore_memory::PAGE_SIZE;
 
@@ -15,10 +17,7 @@ impl provider::Provider for Provider {
     }
 
     fn dealloc_dma(vaddr: usize, size: usize) {
-        let paddr = virt_to_phys(vaddr);
-        for i in 0..size / PAGE_SIZE {
-            dealloc_frame(paddr + i * PAGE_SIZE);
-        }
+        dealloc_frame_contiguous(virt_to_phys(vaddr), size / PAGE_SIZE);
     }
 }
 
@@ -31,9 +30,7 @@ extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr {
 
 #[no_mangle]
 extern "C" fn virtio_dma_dealloc(paddr: PhysAddr, pages: usize) -> i32 {
-    for i in 0..pages {
-        dealloc_frame(paddr + i * PAGE_SIZE);
-    }
+    dealloc_frame_contiguous(paddr, pages);
     trace!("dealloc DMA: paddr={:#x}, pages={}", paddr, pages);
     0
 }
@@ -9,3 +9,27 @@ pub use fbdev::*;
 pub use random::*;
 pub use shm::*;
 pub use tty::*;
+
+use alloc::sync::Arc;
+use rcore_fs::vfs::{make_rdev, INode};
+use rcore_fs_devfs::special::{NullINode, ZeroINode};
+
+/// The driver behind device number `rdev`, for device nodes made by mknod
+pub fn device_by_rdev(rdev: usize) -> Option<Arc<dyn INode>> {
+    let inode: Arc<dyn INode> = if rdev == make_rdev(1, 3) {
+        Arc::new(NullINode::default())
+    } else if rdev == make_rdev(1, 5) {
+        Arc::new(ZeroINode::default())
+    } else if rdev == make_rdev(1, 8) {
+        Arc::new(RandomINode::new(false))
+    } else if rdev == make_rdev(1, 9) {
+        Arc::new(RandomINode::new(true))
+    } else if rdev == make_rdev(5, 0) || rdev == make_rdev(5, 1) {
+        TTY.clone()
+    } else if rdev == make_rdev(29, 0) {
+        Arc::new(Fbdev::default())
+    } else {
+        return None;
+    };
+    Some(inode)
+}
@@ -45,7 +45,7 @@ impl INode for ShmINode {
             nlinks: 1,
             uid: 0,
             gid: 0,
-            rdev: make_rdev(0, 40),
+            rdev: 0,
         })
     }
 
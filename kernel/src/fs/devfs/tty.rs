@@ -1,16 +1,21 @@
+use crate::arch::timer::timer_now;
 use crate::fs::ioctl::*;
 use crate::process::{process_group, Pgid};
 use crate::signal::{send_signal, Signal};
 use crate::signal::{Siginfo, SI_KERNEL};
+use crate::trap::NAIVE_TIMER;
 use crate::{sync::Event, sync::EventBus, syscall::SysError};
 use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::any::Any;
 use core::future::Future;
 use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use core::task::Context;
 use core::task::Poll;
+use core::time::Duration;
 use rcore_fs::vfs::FsError::NotSupported;
 use rcore_fs::vfs::*;
 use spin::{Mutex, RwLock};
@@ -21,7 +26,16 @@ use spin::{Mutex, RwLock};
 pub struct TtyINode {
     /// foreground process group
     foreground_pgid: RwLock<Pgid>,
+    /// input ready to be read
     buf: Mutex<VecDeque<u8>>,
+    /// line being edited in canonical mode
+    line: Mutex<Vec<u8>>,
+    /// a ^D on an empty line, read as end of file
+    eof: AtomicBool,
+    /// size of the pending raw mode read, which VMIN is capped to
+    read_len: AtomicUsize,
+    /// VTIME timer of a raw mode read, with the input length when it was started
+    read_timer: Mutex<Option<(Duration, usize)>>,
     eventbus: Mutex<EventBus>,
     winsize: RwLock<Winsize>,
     termios: RwLock<Termios>,
@@ -37,57 +51,168 @@ pub fn foreground_pgid() -> Pgid {
 
 impl TtyINode {
     pub fn push(&self, c: u8) {
-        let lflag = LocalModes::from_bits_truncate(self.termios.read().lflag);
-        if lflag.contains(LocalModes::ISIG) && [0o3, 0o34, 0o32, 0o31].contains(&(c as i32)) {
-            use Signal::*;
-            let foregroud_processes = process_group(foreground_pgid());
-            match c as i32 {
-                // INTR
-                0o3 => {
-                    for proc in foregroud_processes {
-                        send_signal(
-                            proc,
-                            -1,
-                            Siginfo {
-                                signo: SIGINT as i32,
-                                errno: 0,
-                                code: SI_KERNEL,
-                                field: Default::default(),
-                            },
-                        );
-                    }
-                }
-                _ => warn!("special char {} is unimplented", c),
+        let termios = *self.termios.read();
+        let lflag = LocalModes::from_bits_truncate(termios.lflag);
+        let signal = match c {
+            _ if !lflag.contains(LocalModes::ISIG) => None,
+            // a zero entry disables the character
+            0 => None,
+            c if c == termios.cc[VINTR] => Some(Signal::SIGINT),
+            c if c == termios.cc[VQUIT] => Some(Signal::SIGQUIT),
+            c if c == termios.cc[VSUSP] => Some(Signal::SIGTSTP),
+            _ => None,
+        };
+        if let Some(signal) = signal {
+            // the interrupted line is thrown away
+            if !lflag.contains(LocalModes::NOFLSH) {
+                self.line.lock().clear();
             }
+            for proc in process_group(foreground_pgid()) {
+                send_signal(
+                    proc,
+                    -1,
+                    Siginfo {
+                        signo: signal as i32,
+                        errno: 0,
+                        code: SI_KERNEL,
+                        field: Default::default(),
+                    },
+                );
+            }
+        } else if lflag.contains(LocalModes::ICANON) {
+            self.push_canonical(c, &termios.cc, lflag.contains(LocalModes::ECHO));
         } else {
+            if lflag.contains(LocalModes::ECHO) {
+                echo(&[c]);
+            }
             self.buf.lock().push_back(c);
             self.eventbus.lock().set(Event::READABLE);
         }
     }
 
-    pub fn pop(&self) -> u8 {
-        let mut buf_lock = self.buf.lock();
-        let c = buf_lock.pop_front().unwrap();
-        if buf_lock.len() == 0 {
-            self.eventbus.lock().clear(Event::READABLE);
+    /// Edit the current line, it is readable only once ended by '\n' or ^D
+    fn push_canonical(&self, c: u8, cc: &[u8; NCCS], echo_on: bool) {
+        let mut line = self.line.lock();
+        if c == cc[VERASE] || c == b'\x08' {
+            if line.pop().is_some() && echo_on {
+                echo(b"\x08 \x08");
+            }
+        } else if c == cc[VKILL] {
+            if echo_on {
+                for _ in 0..line.len() {
+                    echo(b"\x08 \x08");
+                }
+            }
+            line.clear();
+        } else if c == cc[VEOF] {
+            if line.is_empty() {
+                self.eof.store(true, Ordering::Relaxed);
+            }
+            self.buf.lock().extend(line.drain(..));
+            self.eventbus.lock().set(Event::READABLE);
+        } else {
+            if echo_on {
+                echo(&[c]);
+            }
+            line.push(c);
+            if c == b'\n' {
+                self.buf.lock().extend(line.drain(..));
+                self.eventbus.lock().set(Event::READABLE);
+            }
         }
-        return c;
     }
 
     pub fn can_read(&self) -> bool {
-        return self.buf.lock().len() > 0;
+        self.buf.lock().len() > 0 || self.eof.load(Ordering::Relaxed)
+    }
+
+    /// Whether a blocked read can go on, which in raw mode depends on VMIN and VTIME
+    fn read_ready(&self) -> bool {
+        let termios = *self.termios.read();
+        if LocalModes::from_bits_truncate(termios.lflag).contains(LocalModes::ICANON) {
+            return self.can_read();
+        }
+        let avail = self.buf.lock().len();
+        self.raw_ready(&termios, avail)
+    }
+
+    /// Whether a raw mode read returns with `avail` bytes of input, see termios(3).
+    /// An expired timer is left in place, only the read itself clears it
+    fn raw_ready(&self, termios: &Termios, avail: usize) -> bool {
+        let vmin = termios.cc[VMIN] as usize;
+        let vtime = termios.cc[VTIME] as u64;
+        let want = vmin.min(self.read_len.load(Ordering::Relaxed)).max(1);
+        if avail >= want {
+            return true;
+        }
+        if vtime == 0 {
+            return false;
+        }
+        // with VMIN 0 the timer starts with the read,
+        // otherwise with the first byte and again with every byte
+        if vmin > 0 && avail == 0 {
+            return false;
+        }
+        let mut timer = self.read_timer.lock();
+        match *timer {
+            Some((deadline, seen)) if seen == avail => timer_now() >= deadline,
+            _ => {
+                let deadline = timer_now() + Duration::from_millis(vtime * 100);
+                *timer = Some((deadline, avail));
+                false
+            }
+        }
     }
 }
 
+/// Print typed input back to the console
+fn echo(bytes: &[u8]) {
+    // raw bytes, the terminal puts multi-byte characters back together
+    print!("{}", unsafe { core::str::from_utf8_unchecked(bytes) });
+}
+
 impl INode for TtyINode {
     /// Read bytes at `offset` into `buf`, return the number of bytes read.
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
-        if self.can_read() {
-            buf[0] = self.pop() as u8;
-            Ok(1)
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let termios = *self.termios.read();
+        let lflag = LocalModes::from_bits_truncate(termios.lflag);
+        // check and drain under the same lock, so concurrent readers can't race
+        let mut input = self.buf.lock();
+        if input.is_empty() && self.eof.swap(false, Ordering::Relaxed) {
+            self.eventbus.lock().clear(Event::READABLE);
+            return Ok(0);
+        }
+        if lflag.contains(LocalModes::ICANON) {
+            if input.is_empty() {
+                return Err(FsError::Again);
+            }
         } else {
-            Err(FsError::Again)
+            self.read_len.store(buf.len(), Ordering::Relaxed);
+            // with VMIN and VTIME both 0 a read never waits
+            let polling = termios.cc[VMIN] == 0 && termios.cc[VTIME] == 0;
+            if !polling && !self.raw_ready(&termios, input.len()) {
+                return Err(FsError::Again);
+            }
+            // the next read starts its own timer
+            *self.read_timer.lock() = None;
+        }
+        let mut len = buf.len().min(input.len());
+        if lflag.contains(LocalModes::ICANON) {
+            // at most one line per read
+            if let Some(end) = input.iter().take(len).position(|&c| c == b'\n') {
+                len = end + 1;
+            }
+        }
+        for (dst, src) in buf.iter_mut().zip(input.drain(..len)) {
+            *dst = src;
+        }
+        if input.is_empty() && !self.eof.load(Ordering::Relaxed) {
+            self.eventbus.lock().clear(Event::READABLE);
         }
+        Ok(len)
     }
 
     /// Write bytes at `offset` from `buf`, return the number of bytes written.
@@ -120,9 +245,16 @@ impl INode for TtyINode {
             type Output = Result<PollStatus>;
 
             fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-                if self.tty.can_read() {
+                if self.tty.read_ready() {
                     return Poll::Ready(self.tty.poll());
                 }
+                // wake up when the VTIME timer runs out
+                if let Some((deadline, _)) = *self.tty.read_timer.lock() {
+                    let waker = cx.waker().clone();
+                    NAIVE_TIMER
+                        .lock()
+                        .add(deadline, Box::new(move |_| waker.wake()));
+                }
                 let waker = cx.waker().clone();
                 self.tty.eventbus.lock().subscribe(Box::new({
                     move |_| {
@@ -141,7 +273,7 @@ impl INode for TtyINode {
         let cmd = cmd as usize;
         match cmd {
             TIOCGPGRP => {
-                // TODO: check the pointer?
+                // user pointers are checked by sys_ioctl
                 let argp = data as *mut i32; // pid_t
                 unsafe { *argp = *self.foreground_pgid.read() };
                 Ok(0)
@@ -168,13 +300,28 @@ impl INode for TtyINode {
                 info!("get lfags: {:?}", lflag);
                 Ok(0)
             }
-            TCSETS => {
+            TCSETS | TCSETSW | TCSETSF => {
                 let termois = data as *const Termios;
                 unsafe {
                     *self.termios.write() = *termois;
                 }
+                // output is written synchronously, so there is nothing to drain
+                if cmd == TCSETSF {
+                    self.line.lock().clear();
+                    self.buf.lock().clear();
+                    self.eof.store(false, Ordering::Relaxed);
+                    self.eventbus.lock().clear(Event::READABLE);
+                }
                 let lflag = LocalModes::from_bits_truncate(self.termios.read().lflag);
                 info!("set lfags: {:?}", lflag);
+                // leaving canonical mode makes the unfinished line readable
+                if !lflag.contains(LocalModes::ICANON) {
+                    let mut line = self.line.lock();
+                    if !line.is_empty() {
+                        self.buf.lock().extend(line.drain(..));
+                        self.eventbus.lock().set(Event::READABLE);
+                    }
+                }
                 Ok(0)
             }
             _ => Err(NotSupported),
@@ -2,11 +2,53 @@
 
 #![allow(dead_code)]
 
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use rcore_fs::dev::*;
 use spin::RwLock;
 
+use crate::drivers::BlockDriverWrapper;
 use crate::sync::SpinNoIrqLock as Mutex;
 
+/// Blocks looked up in the block cache of the root device,
+/// those missed are counted by `BLOCK_READS` below it
+pub static BLOCK_CACHE_LOOKUPS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts the blocks looked up in the block cache `T`
+pub struct CacheCounter<T: Device>(pub T);
+
+impl<T: Device> CacheCounter<T> {
+    fn count(offset: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let log2 = BlockDriverWrapper::BLOCK_SIZE_LOG2;
+        let blocks = ((offset + len - 1) >> log2) - (offset >> log2) + 1;
+        BLOCK_CACHE_LOOKUPS.fetch_add(blocks, Ordering::Relaxed);
+    }
+}
+
+impl<T: Device> Device for CacheCounter<T> {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        Self::count(offset, buf.len());
+        self.0.read_at(offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        Self::count(offset, buf.len());
+        self.0.write_at(offset, buf)
+    }
+    fn sync(&self) -> Result<()> {
+        self.0.sync()
+    }
+}
+
+/// Hits and misses of the block cache of the root device so far
+pub fn block_cache_stats() -> (usize, usize) {
+    let lookups = BLOCK_CACHE_LOOKUPS.load(Ordering::Relaxed);
+    let misses = crate::drivers::BLOCK_READS.load(Ordering::Relaxed);
+    (lookups.saturating_sub(misses), misses)
+}
+
 pub struct MemBuf(RwLock<&'static mut [u8]>);
 
 impl MemBuf {
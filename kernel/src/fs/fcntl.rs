@@ -15,6 +15,10 @@ const F_LINUX_SPECIFIC_BASE: usize = 1024;
 pub const FD_CLOEXEC: usize = 1;
 pub const F_DUPFD_CLOEXEC: usize = F_LINUX_SPECIFIC_BASE + 6;
 
+pub const O_ACCMODE: usize = 0o3;
+pub const O_RDONLY: usize = 0o0;
+pub const O_WRONLY: usize = 0o1;
+pub const O_RDWR: usize = 0o2;
 pub const O_NONBLOCK: usize = 0o4000;
 pub const O_APPEND: usize = 0o2000;
 pub const O_CLOEXEC: usize = 0o2000000; /* set close_on_exec */
@@ -2,30 +2,105 @@
 
 use crate::memory::GlobalFrameAlloc;
 use crate::process::{current_thread, INodeForMap};
-use crate::syscall::{MmapProt, SysResult, TimeSpec};
-use alloc::{string::String, sync::Arc};
+use crate::syscall::{MmapProt, SysError, SysResult, TimeSpec};
+use alloc::collections::BTreeMap;
+use alloc::{string::String, sync::Arc, vec::Vec};
 use core::fmt;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
 
 use rcore_fs::vfs::FsError::{Interrupted, NotSupported};
 use rcore_fs::vfs::{FileType, FsError, INode, MMapArea, Metadata, PollStatus, Result};
 use rcore_memory::memory_set::handler::File;
 
-use crate::fs::fcntl::{O_APPEND, O_NONBLOCK};
+use crate::fs::fcntl::{O_APPEND, O_NONBLOCK, O_RDONLY, O_RDWR, O_WRONLY};
 use crate::sync::SpinLock as Mutex;
-use crate::syscall::SysError::{EAGAIN, ESPIPE};
+use crate::syscall::SysError::{EAGAIN, EBADF, EINVAL, EISDIR, ESPIPE};
 use bitflags::_core::cell::Cell;
 use spin::RwLock;
 
-enum Flock {
-    None = 0,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flock {
     Shared = 1,
     Exclusive = 2,
 }
 
+/// Identifies the locked file as (dev, inode)
+type FlockKey = (usize, usize);
+
+/// Advisory locks held on one file
+#[derive(Default)]
+struct FlockState {
+    shared: usize,
+    exclusive: bool,
+    waiters: Vec<Waker>,
+}
+
+impl FlockState {
+    fn try_acquire(&mut self, kind: Flock) -> bool {
+        match kind {
+            Flock::Shared if !self.exclusive => {
+                self.shared += 1;
+                true
+            }
+            Flock::Exclusive if !self.exclusive && self.shared == 0 => {
+                self.exclusive = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+lazy_static! {
+    static ref FLOCKS: Mutex<BTreeMap<FlockKey, FlockState>> = Mutex::new(BTreeMap::new());
+}
+
+fn flock_release(key: FlockKey, kind: Flock) {
+    let mut flocks = FLOCKS.lock();
+    if let Some(state) = flocks.get_mut(&key) {
+        match kind {
+            Flock::Shared => state.shared -= 1,
+            Flock::Exclusive => state.exclusive = false,
+        }
+        // let every waiter try again
+        for waker in state.waiters.drain(..) {
+            waker.wake();
+        }
+        if state.shared == 0 && !state.exclusive {
+            flocks.remove(&key);
+        }
+    }
+}
+
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct FlockFuture {
+    key: FlockKey,
+    kind: Flock,
+}
+
+impl Future for FlockFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut flocks = FLOCKS.lock();
+        let state = flocks.entry(self.key).or_default();
+        if state.try_acquire(self.kind) {
+            return Poll::Ready(());
+        }
+        state.waiters.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
 struct OpenFileDescription {
     offset: u64,
     options: OpenOptions,
-    flock: Flock,
+    /// advisory lock taken through this description
+    flock: Option<(FlockKey, Flock)>,
 }
 
 impl OpenFileDescription {
@@ -33,11 +108,20 @@ impl OpenFileDescription {
         Arc::new(RwLock::new(OpenFileDescription {
             offset: 0,
             options,
-            flock: Flock::None,
+            flock: None,
         }))
     }
 }
 
+impl Drop for OpenFileDescription {
+    fn drop(&mut self) {
+        // the last close of the description drops its lock
+        if let Some((key, kind)) = self.flock.take() {
+            flock_release(key, kind);
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct FileHandle {
     inode: Arc<dyn INode>,
@@ -91,98 +175,190 @@ impl FileHandle {
         }
     }
 
+    /// Set file status flags, only O_APPEND and O_NONBLOCK can be changed
     pub fn set_options(&self, arg: usize) {
         let options = &mut self.description.write().options;
         options.nonblock = (arg & O_NONBLOCK) != 0;
-        // TODO: handle append
-        // options.append = (arg & O_APPEND) != 0;
+        options.append = (arg & O_APPEND) != 0;
     }
 
-    // pub fn get_options(&self) -> usize {
-    // let options = self.description.read().options;
-    // let mut ret = 0 as usize;
-    // }
+    /// Get access mode and file status flags
+    pub fn get_options(&self) -> usize {
+        let options = self.description.read().options;
+        let mut ret = match (options.read, options.write) {
+            (true, true) => O_RDWR,
+            (false, true) => O_WRONLY,
+            _ => O_RDONLY,
+        };
+        if options.append {
+            ret |= O_APPEND;
+        }
+        if options.nonblock {
+            ret |= O_NONBLOCK;
+        }
+        ret
+    }
 
-    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    pub async fn read(&mut self, buf: &mut [u8]) -> SysResult {
         let offset = self.description.read().offset as usize;
         let len = self.read_at(offset, buf).await?;
         self.description.write().offset += len as u64;
         Ok(len)
     }
 
-    pub async fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
-        // let options = &self.description.read().options;
+    pub async fn read_at(&self, offset: usize, buf: &mut [u8]) -> SysResult {
         if !self.description.read().options.read {
-            return Err(FsError::InvalidParam); // TODO: => EBADF
+            return Err(EBADF);
         }
         if !self.description.read().options.nonblock {
             // block
             loop {
                 match self.inode.read_at(offset, buf) {
                     Ok(read_len) => {
+                        self.accessed();
                         return Ok(read_len);
                     }
                     Err(FsError::Again) => {
                         self.async_poll().await?;
                     }
                     Err(err) => {
-                        return Err(err);
+                        return Err(err.into());
                     }
                 }
             }
         } else {
             let len = self.inode.read_at(offset, buf)?;
+            self.accessed();
             Ok(len)
         }
     }
 
-    pub fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let description = self.description.read();
-        let offset = match description.options.append {
-            true => self.inode.metadata()?.size as u64,
-            false => description.offset,
-        } as usize;
-        drop(description);
-        let len = self.write_at(offset, buf)?;
-        self.description.write().offset += len as u64;
+    fn accessed(&self) {
+        // pipes have no timestamps to keep
+        if !self.pipe {
+            TimeSpec::update_accessed(&self.inode);
+        }
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> SysResult {
+        // don't hold the lock across await
+        let (offset, nonblock) = {
+            let description = self.description.read();
+            // re-read the size right before writing, so appends never overlap
+            let offset = match description.options.append {
+                true => self.inode.metadata()?.size as u64,
+                false => description.offset,
+            } as usize;
+            (offset, description.options.nonblock)
+        };
+        let len = if nonblock {
+            self.write_at(offset, buf)?
+        } else {
+            // block until all data is accepted
+            let mut written = 0;
+            while written < buf.len() {
+                match self.write_at(offset + written, &buf[written..]) {
+                    Ok(0) => break,
+                    Ok(len) => written += len,
+                    Err(EAGAIN) => {
+                        self.async_poll().await?;
+                    }
+                    Err(_) if written > 0 => break,
+                    Err(err) => return Err(err),
+                }
+            }
+            written
+        };
+        // the offset ends up right after the data just written
+        self.description.write().offset = (offset + len) as u64;
         Ok(len)
     }
 
-    pub fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> SysResult {
         if !self.description.read().options.write {
-            return Err(FsError::InvalidParam); // TODO: => EBADF
+            return Err(EBADF);
         }
         let len = self.inode.write_at(offset, buf)?;
-        TimeSpec::update(&self.inode);
+        TimeSpec::update_modified(&self.inode);
         Ok(len)
     }
 
     pub fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
         let mut description = self.description.write();
-        description.offset = match pos {
-            SeekFrom::Start(offset) => offset,
-            SeekFrom::End(offset) => (self.inode.metadata()?.size as i64 + offset) as u64,
-            SeekFrom::Current(offset) => (description.offset as i64 + offset) as u64,
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset as i64),
+            SeekFrom::End(offset) => (self.inode.metadata()?.size as i64).checked_add(offset),
+            SeekFrom::Current(offset) => (description.offset as i64).checked_add(offset),
         };
+        // seeking past the end is fine, but not before the beginning
+        let offset = match offset {
+            Some(offset) if offset >= 0 => offset,
+            _ => return Err(FsError::InvalidParam),
+        };
+        description.offset = offset as u64;
         Ok(description.offset)
     }
 
-    pub fn set_len(&mut self, len: u64) -> Result<()> {
+    pub fn set_len(&mut self, len: u64) -> core::result::Result<(), SysError> {
         if !self.description.read().options.write {
-            return Err(FsError::InvalidParam); // TODO: => EBADF
+            return Err(EBADF);
+        }
+        if self.pipe {
+            return Err(EINVAL);
+        }
+        if self.inode.metadata()?.type_ == FileType::Dir {
+            return Err(EISDIR);
         }
         self.inode.resize(len as usize)?;
+        TimeSpec::update_modified(&self.inode);
         Ok(())
     }
 
     pub fn sync_all(&mut self) -> Result<()> {
+        if self.pipe {
+            return Err(FsError::InvalidParam);
+        }
         self.inode.sync_all()
     }
 
+    /// Like `sync_all`, but skip metadata not needed to read the data back
     pub fn sync_data(&mut self) -> Result<()> {
+        if self.pipe {
+            return Err(FsError::InvalidParam);
+        }
         self.inode.sync_data()
     }
 
+    /// Take, convert or drop (`None`) the advisory lock of the open file description
+    pub async fn flock(&self, kind: Option<Flock>, nonblock: bool) -> SysResult {
+        let metadata = self.inode.metadata()?;
+        let key = (metadata.dev, metadata.inode);
+        let old = {
+            let mut description = self.description.write();
+            if description.flock.map(|(_, held)| held) == kind {
+                return Ok(0);
+            }
+            description.flock.take()
+        };
+        // like linux, a conversion is not atomic: the old lock goes first
+        if let Some((key, held)) = old {
+            flock_release(key, held);
+        }
+        let kind = match kind {
+            Some(kind) => kind,
+            None => return Ok(0),
+        };
+        if nonblock {
+            if !FLOCKS.lock().entry(key).or_default().try_acquire(kind) {
+                return Err(EAGAIN);
+            }
+        } else {
+            FlockFuture { key, kind }.await;
+        }
+        self.description.write().flock = Some((key, kind));
+        Ok(0)
+    }
+
     pub fn metadata(&self) -> Result<Metadata> {
         self.inode.metadata()
     }
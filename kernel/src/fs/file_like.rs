@@ -6,7 +6,7 @@ use crate::fs::epoll::EpollInstance;
 use crate::net::Socket;
 use crate::syscall::{SysError, SysResult};
 use alloc::boxed::Box;
-use rcore_fs::vfs::{MMapArea, PollStatus};
+use rcore_fs::vfs::{FsError, MMapArea, PollStatus};
 
 // TODO: merge FileLike to FileHandle ?
 #[derive(Clone)]
@@ -36,9 +36,16 @@ impl FileLike {
         };
         Ok(len)
     }
-    pub fn write(&mut self, buf: &[u8]) -> SysResult {
+    pub async fn write(&mut self, buf: &[u8]) -> SysResult {
         let len = match self {
-            FileLike::File(file) => file.write(buf)?,
+            FileLike::File(file) => {
+                let len = file.write(buf).await?;
+                // a pipe accepts nothing only when the read end is closed
+                if file.pipe && len == 0 && !buf.is_empty() {
+                    return Err(SysError::EPIPE);
+                }
+                len
+            }
             FileLike::Socket(socket) => socket.write(buf, None)?,
             FileLike::EpollInstance(_) => {
                 return Err(SysError::ENOSYS);
@@ -48,11 +55,13 @@ impl FileLike {
     }
     pub fn ioctl(&mut self, request: usize, arg1: usize, arg2: usize, arg3: usize) -> SysResult {
         match self {
-            FileLike::File(file) => file.io_control(request as u32, arg1).map_err(Into::into),
+            FileLike::File(file) => match file.io_control(request as u32, arg1) {
+                // regular files know no ioctl, isatty() relies on this
+                Err(FsError::NotSupported) => Err(SysError::ENOTTY),
+                ret => ret.map_err(Into::into),
+            },
             FileLike::Socket(socket) => socket.ioctl(request, arg1, arg2, arg3),
-            FileLike::EpollInstance(_) => {
-                return Err(SysError::ENOSYS);
-            }
+            FileLike::EpollInstance(_) => Err(SysError::ENOTTY),
         }
     }
     pub fn mmap(&mut self, area: MMapArea) -> SysResult {
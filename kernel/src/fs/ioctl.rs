@@ -15,6 +15,18 @@ pub const TCSETS: usize = 0x5402;
 #[cfg(target_arch = "mips")]
 pub const TCSETS: usize = 0x540E;
 
+// like TCSETS, after output is drained
+#[cfg(not(target_arch = "mips"))]
+pub const TCSETSW: usize = 0x5403;
+#[cfg(target_arch = "mips")]
+pub const TCSETSW: usize = 0x540F;
+
+// like TCSETSW, and discards pending input
+#[cfg(not(target_arch = "mips"))]
+pub const TCSETSF: usize = 0x5404;
+#[cfg(target_arch = "mips")]
+pub const TCSETSF: usize = 0x5410;
+
 #[cfg(not(target_arch = "mips"))]
 pub const TIOCGPGRP: usize = 0x540F;
 // _IOR('t', 119, int)
@@ -73,7 +85,31 @@ bitflags! {
     }
 }
 
+// indices of control characters in c_cc
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VTIME: usize = 5;
+pub const VSUSP: usize = 10;
+#[cfg(not(target_arch = "mips"))]
+pub const VEOF: usize = 4;
+#[cfg(not(target_arch = "mips"))]
+pub const VMIN: usize = 6;
+#[cfg(target_arch = "mips")]
+pub const VEOF: usize = 16;
+#[cfg(target_arch = "mips")]
+pub const VMIN: usize = 4;
+
+/// Number of control characters in the termios of the kernel,
+/// libc's own struct termios is larger and converted by libc
+#[cfg(not(target_arch = "mips"))]
+pub const NCCS: usize = 19;
+#[cfg(target_arch = "mips")]
+pub const NCCS: usize = 23;
+
 // Ref: https://www.man7.org/linux/man-pages/man3/termios.3.html
+// the layout is the one TCGETS and TCSETS take, without speed fields
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct Termios {
@@ -82,9 +118,7 @@ pub struct Termios {
     pub cflag: u32,
     pub lflag: u32,
     pub line: u8,
-    pub cc: [u8; 32],
-    pub ispeed: u32,
-    pub ospeed: u32,
+    pub cc: [u8; NCCS],
 }
 
 impl Default for Termios {
@@ -99,6 +133,7 @@ impl Default for Termios {
             // IEXTEN | ECHOTCL | ECHOKE ECHO | ECHOE | ECHOK | ISIG | ICANON
             lflag: 0o105073,
             line: 0,
+            #[cfg(not(target_arch = "mips"))]
             cc: [
                 3,   // VINTR Ctrl-C
                 28,  // VQUIT
@@ -117,19 +152,51 @@ impl Default for Termios {
                 23,  // VWERASE
                 22,  // VLNEXT
                 255, // VEOL2
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0,
+            ],
+            #[cfg(target_arch = "mips")]
+            cc: [
+                3,   // VINTR Ctrl-C
+                28,  // VQUIT
+                127, // VERASE
+                21,  // VKILL
+                1,   // VMIN
+                0,   // VTIME
+                255, // VEOL2
+                0,   // VSWTC
+                17,  // VSTART
+                19,  // VSTOP
+                26,  // VSUSP Ctrl-Z
+                0,   // unused
+                18,  // VREPAINT
+                15,  // VDISCARD
+                23,  // VWERASE
+                22,  // VLNEXT
+                4,   // VEOF Ctrl-D
+                255, // VEOL
+                0, 0, 0, 0, 0,
             ],
-            ispeed: 0,
-            ospeed: 0,
         }
     }
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy)]
 pub struct Winsize {
     row: u16,
     ws_col: u16,
     xpixel: u16,
     ypixel: u16,
 }
+
+impl Default for Winsize {
+    fn default() -> Self {
+        // the serial console has no size, assume a classic terminal
+        Winsize {
+            row: 25,
+            ws_col: 80,
+            xpixel: 0,
+            ypixel: 0,
+        }
+    }
+}
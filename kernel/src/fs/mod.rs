@@ -1,6 +1,9 @@
 use alloc::{sync::Arc, vec::Vec};
 
-use rcore_fs::{dev::block_cache::BlockCache, vfs::*};
+use rcore_fs::{
+    dev::{block_cache::BlockCache, Device},
+    vfs::*,
+};
 use rcore_fs_devfs::{
     special::{NullINode, ZeroINode},
     DevFS,
@@ -10,11 +13,15 @@ use rcore_fs_ramfs::RamFS;
 use rcore_fs_sfs::{INodeImpl, SimpleFileSystem};
 
 use self::devfs::{Fbdev, RandomINode};
+use self::procfs::ProcFS;
 
-pub use self::devfs::{ShmINode, TTY};
+pub use self::devfs::{device_by_rdev, ShmINode, TTY};
+pub use self::device::block_cache_stats;
 pub use self::file::*;
 pub use self::file_like::*;
-pub use self::pipe::Pipe;
+pub use self::mount::*;
+pub use self::pipe::{Pipe, PipeEnd};
+pub use self::procfs::own_link_target;
 pub use self::pseudo::*;
 use crate::drivers::{BlockDriver, BlockDriverWrapper};
 
@@ -25,7 +32,9 @@ pub mod fcntl;
 mod file;
 mod file_like;
 pub mod ioctl;
+mod mount;
 mod pipe;
+mod procfs;
 mod pseudo;
 
 // Hard link user programs
@@ -44,8 +53,8 @@ _user_img_end:
 ));
 
 lazy_static! {
-    /// The root of file system
-    pub static ref ROOT_INODE: Arc<dyn INode> = {
+    /// The device of the root file system
+    static ref ROOT_DEVICE: Arc<dyn Device> = {
         #[cfg(not(feature = "link_user"))]
         let device = {
             let driver = BlockDriverWrapper(
@@ -55,7 +64,7 @@ lazy_static! {
                     .clone()
             );
             // enable block cache
-            Arc::new(BlockCache::new(driver, 0x100))
+            Arc::new(device::CacheCounter(BlockCache::new(driver, BLOCK_CACHE_SIZE)))
             // Arc::new(driver)
         };
         #[cfg(feature = "link_user")]
@@ -67,9 +76,13 @@ lazy_static! {
             info!("SFS linked to kernel, from {:08x} to {:08x}", _user_img_start as usize, _user_img_end as usize);
             Arc::new(unsafe { device::MemBuf::new(_user_img_start, _user_img_end) })
         };
+        device
+    };
 
+    /// The root of file system
+    pub static ref ROOT_INODE: Arc<dyn INode> = {
         // use SFS as rootfs
-        let sfs = SimpleFileSystem::open(device).expect("failed to open SFS");
+        let sfs = SimpleFileSystem::open(ROOT_DEVICE.clone()).expect("failed to open SFS");
         let rootfs = MountFS::new(sfs);
         let root = rootfs.root_inode();
 
@@ -80,6 +93,7 @@ lazy_static! {
         devfs.add("random", Arc::new(RandomINode::new(false))).expect("failed to mknod /dev/random");
         devfs.add("urandom", Arc::new(RandomINode::new(true))).expect("failed to mknod /dev/urandom");
         devfs.add("tty", TTY.clone()).expect("failed to mknod /dev/tty");
+        devfs.add("console", TTY.clone()).expect("failed to mknod /dev/console");
         devfs.add("fb0", Arc::new(Fbdev::default())).expect("failed to mknod /dev/fb0");
         devfs.add("shm", Arc::new(ShmINode::default())).expect("failed to mkdir shm");
 
@@ -104,11 +118,28 @@ lazy_static! {
         });
         tmp.mount(ramfs).expect("failed to mount RamFS");
 
+        // mount ProcFS at /proc
+        let proc = root.find(true, "proc").unwrap_or_else(|_| {
+            root.create("proc", FileType::Dir, 0o666).expect("failed to mkdir /proc")
+        });
+        proc.mount(ProcFS::new()).expect("failed to mount ProcFS");
+
         root
     };
 }
 
-pub const FOLLOW_MAX_DEPTH: usize = 3;
+/// Number of blocks kept in the block cache of the root device
+pub const BLOCK_CACHE_SIZE: usize = 0x100;
+
+/// Write the root file system back to its device,
+/// including the dirty blocks in the block cache
+pub fn sync() -> Result<()> {
+    ROOT_INODE.fs().sync()?;
+    ROOT_DEVICE.sync().map_err(|_| FsError::DeviceError)
+}
+
+/// Max number of symlinks followed in a path lookup, exceeding it gives ELOOP
+pub const FOLLOW_MAX_DEPTH: usize = 40;
 
 pub trait INodeExt {
     fn read_as_vec(&self) -> Result<Vec<u8>>;
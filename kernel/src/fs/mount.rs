@@ -0,0 +1,90 @@
+//! Mount table of filesystems attached at runtime
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use rcore_fs::vfs::*;
+use rcore_fs_mountfs::{MNode, MountFS};
+
+use crate::sync::SpinLock as Mutex;
+
+/// A filesystem attached by mount(2)
+pub struct MountEntry {
+    pub source: String,
+    /// Absolute path of the mount point
+    pub target: String,
+    pub fstype: String,
+    pub fs: Arc<MountFS>,
+    /// The filesystem mounted as `fs`, detached by umount(2)
+    pub detachable: Arc<DetachableFS>,
+}
+
+lazy_static! {
+    /// Filesystems mounted at runtime, those set up at boot are not listed
+    pub static ref MOUNTS: Mutex<Vec<MountEntry>> = Mutex::new(Vec::new());
+    /// Mount points unmounted so far, reused when mounted on again
+    pub static ref DETACHED: Mutex<Vec<(Arc<MountFS>, Arc<DetachableFS>)>> =
+        Mutex::new(Vec::new());
+}
+
+/// A filesystem mounted at runtime.
+///
+/// The mount layer can't remove a mount point, so filesystems are mounted
+/// through this. Once detached, its root is the directory it covered again.
+pub struct DetachableFS {
+    fs: Mutex<Option<Arc<dyn FileSystem>>>,
+    /// The mount point, as it was before mounting
+    covered: Arc<MNode>,
+}
+
+impl DetachableFS {
+    pub fn new(fs: Arc<dyn FileSystem>, covered: Arc<MNode>) -> Arc<Self> {
+        Arc::new(DetachableFS {
+            fs: Mutex::new(Some(fs)),
+            covered,
+        })
+    }
+
+    pub fn attach(&self, fs: Arc<dyn FileSystem>) {
+        *self.fs.lock() = Some(fs);
+    }
+
+    /// Show the covered directory again,
+    /// files still open in the filesystem keep working until closed
+    pub fn detach(&self) {
+        *self.fs.lock() = None;
+    }
+
+    /// Check whether this was mounted on the directory with `metadata`
+    pub fn covers(&self, metadata: &Metadata) -> bool {
+        match self.covered.metadata() {
+            Ok(covered) => covered.dev == metadata.dev && covered.inode == metadata.inode,
+            Err(_) => false,
+        }
+    }
+}
+
+impl FileSystem for DetachableFS {
+    fn sync(&self) -> Result<()> {
+        let fs = self.fs.lock().clone();
+        match fs {
+            Some(fs) => fs.sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        let fs = self.fs.lock().clone();
+        match fs {
+            Some(fs) => fs.root_inode(),
+            None => self.covered.clone(),
+        }
+    }
+
+    fn info(&self) -> FsInfo {
+        let fs = self.fs.lock().clone();
+        match fs {
+            Some(fs) => fs.info(),
+            None => self.covered.fs().info(),
+        }
+    }
+}
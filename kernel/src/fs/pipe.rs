@@ -3,7 +3,8 @@
 use crate::sync::{Event, EventBus, SpinNoIrqLock as Mutex};
 use crate::syscall::SysError::EAGAIN;
 use alloc::boxed::Box;
-use alloc::collections::BTreeSet;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Weak;
 use alloc::{collections::vec_deque::VecDeque, sync::Arc};
 use core::any::Any;
 use core::cmp::min;
@@ -16,72 +17,211 @@ use core::{
 use rcore_fs::vfs::FsError::Again;
 use rcore_fs::vfs::*;
 
+/// Maximum number of bytes buffered in a pipe
+const PIPE_CAPACITY: usize = 0x10000;
+
 #[derive(Clone, PartialEq)]
 pub enum PipeEnd {
     Read,
     Write,
+    /// a FIFO opened with O_RDWR holds both ends
+    Both,
 }
 
 pub struct PipeData {
     buf: VecDeque<u8>,
     eventbus: EventBus,
-    /// number of pipe ends
-    end_cnt: i32,
+    /// number of read ends
+    readers: usize,
+    /// number of write ends
+    writers: usize,
+    /// whether a read end was ever opened, a FIFO starts without any
+    had_reader: bool,
+    /// whether a write end was ever opened
+    had_writer: bool,
+}
+
+impl PipeData {
+    fn new() -> Self {
+        let mut eventbus = EventBus::default();
+        eventbus.set(Event::WRITABLE);
+        PipeData {
+            buf: VecDeque::new(),
+            eventbus,
+            readers: 0,
+            writers: 0,
+            had_reader: false,
+            had_writer: false,
+        }
+    }
+
+    /// All read ends were closed
+    fn readers_gone(&self) -> bool {
+        self.had_reader && self.readers == 0
+    }
+
+    /// All write ends were closed
+    fn writers_gone(&self) -> bool {
+        self.had_writer && self.writers == 0
+    }
+
+    /// Wake up everyone waiting on the pipe, even if `event` is already set
+    fn notify(&mut self, event: Event) {
+        self.eventbus.clear(event);
+        self.eventbus.set(event);
+    }
+}
+
+lazy_static! {
+    /// Pipes behind the FIFOs being open, by (dev, inode) of the FIFO
+    static ref FIFOS: Mutex<BTreeMap<(usize, usize), Weak<Mutex<PipeData>>>> =
+        Mutex::new(BTreeMap::new());
 }
 
-#[derive(Clone)]
 pub struct Pipe {
     data: Arc<Mutex<PipeData>>,
     direction: PipeEnd,
 }
 
+impl Clone for Pipe {
+    fn clone(&self) -> Self {
+        Pipe::new_end(self.data.clone(), self.direction.clone())
+    }
+}
+
 impl Drop for Pipe {
     fn drop(&mut self) {
         // pipe end closed
         let mut data = self.data.lock();
-        data.end_cnt -= 1;
-        data.eventbus.set(Event::CLOSED);
+        if self.direction != PipeEnd::Write {
+            data.readers -= 1;
+        }
+        if self.direction != PipeEnd::Read {
+            data.writers -= 1;
+        }
+        data.notify(Event::CLOSED);
     }
 }
 
 impl Pipe {
     /// Create a pair of INode: (read, write)
     pub fn create_pair() -> (Pipe, Pipe) {
-        let inner = PipeData {
-            buf: VecDeque::new(),
-            eventbus: EventBus::default(),
-            end_cnt: 2, // one read, one write
-        };
-        let data = Arc::new(Mutex::new(inner));
+        let data = Arc::new(Mutex::new(PipeData::new()));
         (
-            Pipe {
-                data: data.clone(),
-                direction: PipeEnd::Read,
-            },
-            Pipe {
-                data: data.clone(),
-                direction: PipeEnd::Write,
-            },
+            Pipe::new_end(data.clone(), PipeEnd::Read),
+            Pipe::new_end(data, PipeEnd::Write),
         )
     }
 
+    /// Open an end of the pipe shared by everyone who opened the FIFO `key`
+    pub fn open_fifo(key: (usize, usize), direction: PipeEnd) -> Pipe {
+        let mut fifos = FIFOS.lock();
+        // forget pipes whose ends are all closed
+        fifos.retain(|_, data| data.strong_count() > 0);
+        let data = match fifos.get(&key).and_then(|data| data.upgrade()) {
+            Some(data) => data,
+            None => {
+                let data = Arc::new(Mutex::new(PipeData::new()));
+                fifos.insert(key, Arc::downgrade(&data));
+                data
+            }
+        };
+        Pipe::new_end(data, direction)
+    }
+
+    fn new_end(data: Arc<Mutex<PipeData>>, direction: PipeEnd) -> Pipe {
+        {
+            let mut inner = data.lock();
+            if direction != PipeEnd::Write {
+                inner.readers += 1;
+                inner.had_reader = true;
+                // writers waiting for a reader can go on
+                inner.notify(Event::WRITABLE);
+            }
+            if direction != PipeEnd::Read {
+                inner.writers += 1;
+                inner.had_writer = true;
+                // readers waiting for a writer can go on
+                inner.notify(Event::READABLE);
+            }
+        }
+        Pipe { data, direction }
+    }
+
+    /// Whether an end of the other direction is open
+    pub fn has_peer(&self) -> bool {
+        let data = self.data.lock();
+        match self.direction {
+            PipeEnd::Read => data.writers > 0,
+            PipeEnd::Write => data.readers > 0,
+            PipeEnd::Both => true,
+        }
+    }
+
+    /// Wait until an end of the other direction is opened
+    pub async fn wait_for_peer(&self) {
+        #[must_use = "future does nothing unless polled/`await`-ed"]
+        struct PeerFuture<'a> {
+            pipe: &'a Pipe,
+        };
+
+        impl<'a> Future for PeerFuture<'a> {
+            type Output = ();
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                // check and subscribe under the same lock, or the peer may be missed
+                let mut data = self.pipe.data.lock();
+                let has_peer = match self.pipe.direction {
+                    PipeEnd::Read => data.writers > 0,
+                    PipeEnd::Write => data.readers > 0,
+                    PipeEnd::Both => true,
+                };
+                if has_peer {
+                    return Poll::Ready(());
+                }
+                let waker = cx.waker().clone();
+                data.eventbus.subscribe(Box::new({
+                    move |_| {
+                        waker.wake_by_ref();
+                        true
+                    }
+                }));
+                Poll::Pending
+            }
+        }
+
+        PeerFuture { pipe: self }.await
+    }
+
     fn can_read(&self) -> bool {
-        if let PipeEnd::Read = self.direction {
+        if self.direction != PipeEnd::Write {
             // true
             let data = self.data.lock();
-            data.buf.len() > 0 || data.end_cnt < 2 // other end closed
+            data.buf.len() > 0 || data.writers_gone() // EOF
         } else {
             false
         }
     }
 
     fn can_write(&self) -> bool {
-        if let PipeEnd::Write = self.direction {
-            self.data.lock().end_cnt == 2
+        if self.direction != PipeEnd::Read {
+            let data = self.data.lock();
+            data.buf.len() < PIPE_CAPACITY && data.readers > 0
         } else {
             false
         }
     }
+
+    /// Whether the other end of this pipe has been closed
+    fn peer_closed(&self) -> bool {
+        let data = self.data.lock();
+        match self.direction {
+            PipeEnd::Read => data.writers_gone(),
+            PipeEnd::Write => data.readers_gone(),
+            // it is its own peer
+            PipeEnd::Both => false,
+        }
+    }
 }
 
 impl INode for Pipe {
@@ -89,9 +229,10 @@ impl INode for Pipe {
         if buf.len() == 0 {
             return Ok(0);
         }
-        if let PipeEnd::Read = self.direction {
+        if self.direction != PipeEnd::Write {
             let mut data = self.data.lock();
-            if data.buf.len() == 0 && data.end_cnt == 2 {
+            // a FIFO without any writer yet waits for one
+            if data.buf.len() == 0 && !data.writers_gone() {
                 Err(Again)
             } else {
                 let len = min(buf.len(), data.buf.len());
@@ -101,6 +242,8 @@ impl INode for Pipe {
                 if data.buf.len() == 0 {
                     data.eventbus.clear(Event::READABLE);
                 }
+                // wake up writers blocked on a full pipe
+                data.eventbus.set(Event::WRITABLE);
                 Ok(len)
             }
         } else {
@@ -109,13 +252,30 @@ impl INode for Pipe {
     }
 
     fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
-        if let PipeEnd::Write = self.direction {
+        if buf.len() == 0 {
+            return Ok(0);
+        }
+        if self.direction != PipeEnd::Read {
             let mut data = self.data.lock();
-            for c in buf {
-                data.buf.push_back(*c);
+            if data.readers_gone() {
+                // read end closed
+                return Ok(0);
+            }
+            if data.readers == 0 {
+                // a FIFO without any reader yet
+                return Err(Again);
             }
+            let space = PIPE_CAPACITY - data.buf.len();
+            if space == 0 {
+                return Err(Again);
+            }
+            let len = min(space, buf.len());
+            data.buf.extend(buf[..len].iter());
             data.eventbus.set(Event::READABLE);
-            Ok(buf.len())
+            if data.buf.len() == PIPE_CAPACITY {
+                data.eventbus.clear(Event::WRITABLE);
+            }
+            Ok(len)
         } else {
             Ok(0)
         }
@@ -125,7 +285,8 @@ impl INode for Pipe {
         Ok(PollStatus {
             read: self.can_read(),
             write: self.can_write(),
-            error: false,
+            // reported as POLLHUP
+            error: self.peer_closed(),
         })
     }
 
@@ -141,7 +302,7 @@ impl INode for Pipe {
             type Output = Result<PollStatus>;
 
             fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-                if self.pipe.can_read() || self.pipe.can_write() {
+                if self.pipe.can_read() || self.pipe.can_write() || self.pipe.peer_closed() {
                     return Poll::Ready(self.pipe.poll());
                 }
                 let waker = cx.waker().clone();
@@ -0,0 +1,381 @@
+//! Read-only process information filesystem, mounted at /proc
+//!
+//! Nothing is stored, every read is generated from the process table.
+//! Files and directories are read with no lock of the reader held, so they
+//! lock the process they show. Links and lookups run under the reader's own
+//! process lock, so they only try-lock: a busy process reads as EAGAIN there.
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::any::Any;
+
+use rcore_fs::vfs::*;
+
+use super::FileLike;
+use crate::process::{current_thread, process, Process, PROCESSES};
+use crate::sync::SpinNoIrqLock as Mutex;
+
+/// Device number of /proc
+const PROC_DEV: usize = 0x50;
+
+lazy_static! {
+    static ref PROC_FS: Arc<ProcFS> = Arc::new(ProcFS);
+}
+
+pub struct ProcFS;
+
+impl ProcFS {
+    pub fn new() -> Arc<Self> {
+        PROC_FS.clone()
+    }
+}
+
+impl FileSystem for ProcFS {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        Arc::new(ProcINode(Node::Root))
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            bsize: 0,
+            frsize: 0,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Node {
+    Root,
+    /// /proc/self
+    SelfLink,
+    Meminfo,
+    /// /proc/<pid>
+    Pid(usize),
+    Status(usize),
+    Maps(usize),
+    Exe(usize),
+    /// /proc/<pid>/fd
+    FdDir(usize),
+    /// /proc/<pid>/fd/<fd>
+    Fd(usize, usize),
+}
+
+/// Slots of the nodes under /proc/<pid> in their inode numbers
+const PID_SLOT_BITS: usize = 17;
+const SLOT_FD_BASE: usize = 0x10;
+
+impl Node {
+    fn id(&self) -> usize {
+        let (pid, slot) = match *self {
+            Node::Root => return 1,
+            Node::SelfLink => return 2,
+            Node::Meminfo => return 3,
+            Node::Pid(pid) => (pid, 0),
+            Node::Status(pid) => (pid, 1),
+            Node::Maps(pid) => (pid, 2),
+            Node::Exe(pid) => (pid, 3),
+            Node::FdDir(pid) => (pid, 4),
+            Node::Fd(pid, fd) => (pid, SLOT_FD_BASE + fd),
+        };
+        (pid + 1) << PID_SLOT_BITS | slot
+    }
+
+    /// The link of `pid` with inode number `id`, if it is one
+    fn link_of(pid: usize, id: usize) -> Option<Node> {
+        if id >> PID_SLOT_BITS != pid + 1 {
+            return None;
+        }
+        match id & ((1 << PID_SLOT_BITS) - 1) {
+            3 => Some(Node::Exe(pid)),
+            slot if slot >= SLOT_FD_BASE => Some(Node::Fd(pid, slot - SLOT_FD_BASE)),
+            _ => None,
+        }
+    }
+
+    fn type_(&self) -> FileType {
+        match self {
+            Node::Root | Node::Pid(_) | Node::FdDir(_) => FileType::Dir,
+            Node::SelfLink | Node::Exe(_) | Node::Fd(..) => FileType::SymLink,
+            _ => FileType::File,
+        }
+    }
+
+    /// Names in the directory, after "." and ".."
+    fn entry(&self, index: usize) -> Result<String> {
+        let names: &[&str] = match self {
+            Node::Root => &["self", "meminfo"],
+            Node::Pid(_) => &["status", "maps", "exe", "fd"],
+            Node::FdDir(pid) => {
+                return lock(*pid)?
+                    .lock()
+                    .files
+                    .keys()
+                    .nth(index)
+                    .map(|fd| fd.to_string())
+                    .ok_or(FsError::EntryNotFound);
+            }
+            _ => return Err(FsError::NotDir),
+        };
+        if let Some(name) = names.get(index) {
+            return Ok(name.to_string());
+        }
+        // then the processes, no process lock is needed to list them
+        match self {
+            Node::Root => PROCESSES
+                .read()
+                .keys()
+                .nth(index - names.len())
+                .map(|pid| pid.to_string())
+                .ok_or(FsError::EntryNotFound),
+            _ => Err(FsError::EntryNotFound),
+        }
+    }
+
+    fn find(&self, name: &str) -> Result<Node> {
+        if self.type_() != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        let node = match (self, name) {
+            (_, ".") => *self,
+            (Node::Root, "..") => Node::Root,
+            (Node::Root, "self") => Node::SelfLink,
+            (Node::Root, "meminfo") => Node::Meminfo,
+            (Node::Root, pid) => {
+                let pid = pid.parse().map_err(|_| FsError::EntryNotFound)?;
+                lock(pid)?;
+                Node::Pid(pid)
+            }
+            (Node::Pid(_), "..") => Node::Root,
+            (Node::Pid(pid), "status") => Node::Status(*pid),
+            (Node::Pid(pid), "maps") => Node::Maps(*pid),
+            (Node::Pid(pid), "exe") => Node::Exe(*pid),
+            (Node::Pid(pid), "fd") => Node::FdDir(*pid),
+            (Node::FdDir(pid), "..") => Node::Pid(*pid),
+            (Node::FdDir(pid), fd) => {
+                let fd = fd.parse().map_err(|_| FsError::EntryNotFound)?;
+                // a busy process, maybe the one looking up, is checked on read
+                if let Some(proc) = lock(*pid)?.try_lock() {
+                    if !proc.files.contains_key(&fd) {
+                        return Err(FsError::EntryNotFound);
+                    }
+                }
+                Node::Fd(*pid, fd)
+            }
+            _ => return Err(FsError::EntryNotFound),
+        };
+        Ok(node)
+    }
+
+    fn content(&self) -> Result<String> {
+        match *self {
+            Node::SelfLink => current_pid().map(|pid| pid.to_string()),
+            Node::Meminfo => Ok(meminfo()),
+            Node::Status(pid) => Ok(status(&lock(pid)?.lock())),
+            Node::Maps(pid) => Ok(maps(&lock(pid)?.lock())),
+            node => {
+                let pid = match node {
+                    Node::Exe(pid) | Node::Fd(pid, _) => pid,
+                    _ => return Err(FsError::IsDir),
+                };
+                let proc = lock(pid)?;
+                let proc = proc.try_lock().ok_or(FsError::Again)?;
+                link_target(&proc, node).ok_or(FsError::EntryNotFound)
+            }
+        }
+    }
+}
+
+/// The process `pid`
+fn lock(pid: usize) -> Result<Arc<Mutex<Process>>> {
+    process(pid).ok_or(FsError::EntryNotFound)
+}
+
+/// Pid of the current process, found without locking it
+fn current_pid() -> Result<usize> {
+    let thread = current_thread().ok_or(FsError::EntryNotFound)?;
+    PROCESSES
+        .read()
+        .iter()
+        .find(|(_, proc)| Arc::ptr_eq(proc, &thread.proc))
+        .map(|(&pid, _)| pid)
+        .ok_or(FsError::EntryNotFound)
+}
+
+fn link_target(proc: &Process, node: Node) -> Option<String> {
+    match node {
+        Node::Exe(_) => Some(proc.exec_path.clone()),
+        Node::Fd(_, fd) => Some(match proc.files.get(&fd)? {
+            FileLike::File(file) => file.path.clone(),
+            FileLike::Socket(_) => String::from("socket:[0]"),
+            FileLike::EpollInstance(_) => String::from("anon_inode:[eventpoll]"),
+        }),
+        _ => None,
+    }
+}
+
+/// Target of the /proc link `metadata` belongs to, if it is one of `proc`
+/// itself. Path lookup runs with the process locked, so it can't read
+/// these links the usual way.
+pub fn own_link_target(metadata: &Metadata, proc: &Process) -> Option<String> {
+    if metadata.dev != PROC_DEV || metadata.type_ != FileType::SymLink {
+        return None;
+    }
+    link_target(proc, Node::link_of(proc.pid.get(), metadata.inode)?)
+}
+
+pub struct ProcINode(Node);
+
+impl INode for ProcINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let content = self.0.content()?;
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return Ok(0);
+        }
+        let len = (content.len() - offset).min(buf.len());
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let type_ = self.0.type_();
+        let (mode, nlinks) = match type_ {
+            FileType::Dir => (0o555, 2),
+            FileType::SymLink => (0o777, 1),
+            _ => (0o444, 1),
+        };
+        Ok(Metadata {
+            dev: PROC_DEV,
+            inode: self.0.id(),
+            // generated on read, so the size is unknown like on linux
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_,
+            mode,
+            nlinks,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        Ok(Arc::new(ProcINode(self.0.find(name)?)))
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            id => self.0.entry(id - 2),
+        }
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        PROC_FS.clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Content of /proc/<pid>/status
+fn status(proc: &Process) -> String {
+    let state = if proc.exited() {
+        "Z (zombie)"
+    } else {
+        "R (running)"
+    };
+    let mut status = format!(
+        "Name:\t{}\nState:\t{}\nPid:\t{}\nPPid:\t{}\nThreads:\t{}\n",
+        proc.exec_path,
+        state,
+        proc.pid,
+        proc.parent.0,
+        proc.threads.len()
+    );
+    let size: usize = proc
+        .vm
+        .lock()
+        .iter()
+        .map(|area| area.range())
+        .map(|(start, end)| end - start)
+        .sum();
+    status += &format!("VmSize:\t{} kB\n", size / 1024);
+    status
+}
+
+/// Content of /proc/<pid>/maps
+fn maps(proc: &Process) -> String {
+    let mut maps = String::new();
+    for area in proc.vm.lock().iter() {
+        let (start, end) = area.range();
+        let attr = area.attr();
+        let access = !attr.is_inaccessible();
+        maps += &format!(
+            "{:08x}-{:08x} {}{}{}p 00000000 00:00 0\t[{}]\n",
+            start,
+            end,
+            if access { 'r' } else { '-' },
+            if access && !attr.is_readonly() {
+                'w'
+            } else {
+                '-'
+            },
+            if access && attr.is_execute() {
+                'x'
+            } else {
+                '-'
+            },
+            area.name()
+        );
+    }
+    maps
+}
+
+/// Content of /proc/meminfo
+fn meminfo() -> String {
+    use crate::memory::{FRAMES_IN_USE, FRAMES_TOTAL};
+    use core::sync::atomic::Ordering;
+    use rcore_memory::PAGE_SIZE;
+    let total = FRAMES_TOTAL.load(Ordering::Relaxed) * PAGE_SIZE / 1024;
+    let used = FRAMES_IN_USE.load(Ordering::Relaxed) * PAGE_SIZE / 1024;
+    let (hits, misses) = super::block_cache_stats();
+    format!(
+        "MemTotal:\t{} kB\nMemFree:\t{} kB\nMemUsed:\t{} kB\nBlockCacheHits:\t{}\nBlockCacheMisses:\t{}\n",
+        total,
+        total.saturating_sub(used),
+        used,
+        hits,
+        misses
+    )
+}
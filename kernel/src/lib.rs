@@ -52,6 +52,7 @@ pub mod lkm;
 pub mod memory;
 pub mod net;
 pub mod process;
+pub mod random;
 #[cfg(feature = "hypervisor")]
 pub mod rvm;
 pub mod shell;
@@ -8,6 +8,7 @@ use bitmap_allocator::BitAlloc;
 use buddy_system_allocator::Heap;
 use core::mem;
 use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use log::*;
 use rcore_memory::*;
 
@@ -30,6 +31,12 @@ pub type FrameAlloc = bitmap_allocator::BitAlloc1M;
 
 pub static FRAME_ALLOCATOR: SpinNoIrqLock<FrameAlloc> = SpinNoIrqLock::new(FrameAlloc::DEFAULT);
 
+/// Number of frames managed by `FRAME_ALLOCATOR`
+pub static FRAMES_TOTAL: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of frames allocated by `GlobalFrameAlloc`
+pub static FRAMES_IN_USE: AtomicUsize = AtomicUsize::new(0);
+
 /// Convert physical address to virtual address
 #[inline]
 #[cfg(not(mipsel))]
@@ -71,6 +78,9 @@ impl FrameAllocator for GlobalFrameAlloc {
             .alloc()
             .map(|id| id * PAGE_SIZE + MEMORY_OFFSET);
         trace!("Allocate frame: {:x?}", ret);
+        if ret.is_some() {
+            FRAMES_IN_USE.fetch_add(1, Ordering::Relaxed);
+        }
         ret
         // TODO: try to swap out when alloc failed
     }
@@ -81,6 +91,9 @@ impl FrameAllocator for GlobalFrameAlloc {
             .alloc_contiguous(size, align_log2)
             .map(|id| id * PAGE_SIZE + MEMORY_OFFSET);
         trace!("Allocate frame: {:x?}", ret);
+        if ret.is_some() {
+            FRAMES_IN_USE.fetch_add(size, Ordering::Relaxed);
+        }
         ret
         // TODO: try to swap out when alloc failed
     }
@@ -89,6 +102,7 @@ impl FrameAllocator for GlobalFrameAlloc {
         FRAME_ALLOCATOR
             .lock()
             .dealloc((target - MEMORY_OFFSET) / PAGE_SIZE);
+        FRAMES_IN_USE.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -101,6 +115,10 @@ pub fn dealloc_frame(target: usize) {
 pub fn alloc_frame_contiguous(size: usize, align_log2: usize) -> Option<usize> {
     GlobalFrameAlloc.alloc_contiguous(size, align_log2)
 }
+/// Free `size` frames starting at `target`, from `alloc_frame_contiguous`
+pub fn dealloc_frame_contiguous(target: usize, size: usize) {
+    GlobalFrameAlloc.dealloc_contiguous(target, size);
+}
 
 pub struct KernelStack(usize);
 const KSTACK_SIZE: usize = 0x4000; //16KB
@@ -12,7 +12,7 @@ use crate::process::thread::THREADS;
 use crate::sync::{Event, EventBus, SpinLock, SpinNoIrqLock as Mutex};
 use crate::{
     signal::{Siginfo, Signal, SignalAction, SignalStack, Sigset},
-    syscall::handle_syscall,
+    syscall::{handle_syscall, SysError},
 };
 use alloc::{
     boxed::Box, collections::BTreeMap, collections::VecDeque, string::String, sync::Arc,
@@ -67,6 +67,12 @@ impl fmt::Display for Pid {
     }
 }
 
+/// Default RLIMIT_NOFILE, both soft and hard
+pub const DEFAULT_FILE_LIMIT: usize = 1024;
+
+/// Ceiling of the hard RLIMIT_NOFILE, like linux's nr_open
+pub const MAX_FILE_LIMIT: usize = 0x10000;
+
 /// process group id type
 pub type Pgid = i32;
 
@@ -80,9 +86,42 @@ pub struct Process {
     /// Current working dirctory
     pub cwd: String,
 
+    /// Permission bits cleared from the mode of newly created files
+    pub umask: usize,
+
+    /// Real user id
+    pub uid: usize,
+
+    /// Effective user id, used for permission checks
+    pub euid: usize,
+
+    /// Saved set-user-id, which the effective one may be switched back to
+    pub suid: usize,
+
+    /// Real group id
+    pub gid: usize,
+
+    /// Effective group id, used for permission checks
+    pub egid: usize,
+
+    /// Saved set-group-id
+    pub sgid: usize,
+
+    /// Soft RLIMIT_NOFILE, every fd is below it
+    pub file_limit: usize,
+
+    /// Hard RLIMIT_NOFILE, the soft limit can't be raised above it
+    pub file_limit_max: usize,
+
     /// Executable path
     pub exec_path: String,
 
+    /// Start of the heap, the program break never goes below it
+    pub brk_start: usize,
+
+    /// Program break, i.e. end of the heap
+    pub brk: usize,
+
     /// Futex
     pub futexes: BTreeMap<usize, Arc<Futex>>,
 
@@ -112,6 +151,9 @@ pub struct Process {
     /// Exit code
     pub exit_code: usize,
 
+    /// Signal that killed the process, 0 if it exited by itself
+    pub exit_signal: usize,
+
     // delivered signals, tid specified thread, -1 stands for any thread
     // TODO: implement with doubly linked list, but how to do it in rust safely? [doggy]
     pub sig_queue: VecDeque<(Siginfo, isize)>,
@@ -167,20 +209,19 @@ pub fn add_to_process_table(proc: Arc<Mutex<Process>>, pid: Pid) {
 
 impl Process {
     /// Get lowest free fd
-    fn get_free_fd(&self) -> usize {
-        (0..).find(|i| !self.files.contains_key(i)).unwrap()
-    }
-
-    /// get the lowest available fd great than or equal to arg
-    pub fn get_free_fd_from(&self, arg: usize) -> usize {
-        (arg..).find(|i| !self.files.contains_key(i)).unwrap()
+    /// get the lowest available fd great than or equal to arg,
+    /// EMFILE if there is none below the limit
+    pub fn get_free_fd_from(&self, arg: usize) -> Result<usize, SysError> {
+        (arg..self.file_limit)
+            .find(|i| !self.files.contains_key(i))
+            .ok_or(SysError::EMFILE)
     }
 
     /// Add a file to the process, return its fd.
-    pub fn add_file(&mut self, file_like: FileLike) -> usize {
-        let fd = self.get_free_fd();
+    pub fn add_file(&mut self, file_like: FileLike) -> Result<usize, SysError> {
+        let fd = self.get_free_fd_from(0)?;
         self.files.insert(fd, file_like);
-        fd
+        Ok(fd)
     }
 
     /// Get futex by addr
@@ -210,6 +251,21 @@ impl Process {
         }
         self.exit_code = exit_code;
 
+        // hand children over to init, which reaps them from now on
+        if !self.pid.is_init() && !self.children.is_empty() {
+            if let Some(init) = process(Pid::INIT) {
+                let mut init_proc = init.lock();
+                for (pid, child) in self.children.drain(..) {
+                    if let Some(c) = child.upgrade() {
+                        c.lock().parent = (Pid(Pid::INIT), Arc::downgrade(&init));
+                        init_proc.children.push((pid, child));
+                    }
+                }
+                // some of them may have exited already
+                init_proc.eventbus.lock().set(Event::CHILD_PROCESS_QUIT);
+            }
+        }
+
         // quit all threads
         // this must be after setting the value of subprocess, or the threads will be treated exit before actually exits
         // remove from thread table
@@ -222,6 +278,12 @@ impl Process {
         info!("process {} exit with {}", self.pid.get(), exit_code);
     }
 
+    /// Terminate the process by signal `signo`, which its parent sees with WIFSIGNALED
+    pub fn exit_by_signal(&mut self, signo: usize) {
+        self.exit_signal = signo;
+        self.exit(signo + 128);
+    }
+
     pub fn exited(&self) -> bool {
         self.threads.is_empty()
     }
@@ -63,12 +63,13 @@ pub trait ElfExt {
 
     /// Append current ELF file as interpreter into given memory set.
     /// This will insert the interpreter it a place which is "good enough" (since ld.so should be PIC).
+    /// Return the page right after the interpreter.
     fn append_as_interpreter(
         &self,
         inode: &Arc<dyn INode>,
         memory_set: &mut MemorySet,
         bias: usize,
-    );
+    ) -> usize;
 
     /// Get virtual address of PHDR section if it has.
     fn get_phdr_vaddr(&self) -> Option<u64>;
@@ -102,9 +103,14 @@ impl ElfExt for ElfFile<'_> {
 
         Page::of_addr(farthest_memory + PAGE_SIZE).start_address()
     }
-    fn append_as_interpreter(&self, inode: &Arc<dyn INode>, ms: &mut MemorySet, bias: usize) {
+    fn append_as_interpreter(
+        &self,
+        inode: &Arc<dyn INode>,
+        ms: &mut MemorySet,
+        bias: usize,
+    ) -> usize {
         debug!("inserting interpreter from ELF");
-
+        let mut farthest_memory: usize = bias;
         for ph in self.program_iter() {
             if ph.get_type() != Ok(Type::Load) {
                 continue;
@@ -121,8 +127,13 @@ impl ElfExt for ElfFile<'_> {
                     allocator: GlobalFrameAlloc,
                 },
                 "elf-interp",
-            )
+            );
+            if ph.virtual_addr() as usize + ph.mem_size() as usize + bias > farthest_memory {
+                farthest_memory = ph.virtual_addr() as usize + ph.mem_size() as usize + bias;
+            }
         }
+
+        Page::of_addr(farthest_memory + PAGE_SIZE).start_address()
     }
     fn get_interpreter(&self) -> Result<&str, &str> {
         let header = self
@@ -1,6 +1,6 @@
 use super::{
     abi::{self, ProcInitInfo},
-    add_to_process_table, Pid, Process, PROCESSORS,
+    add_to_process_table, Pid, Process, DEFAULT_FILE_LIMIT, PROCESSORS,
 };
 use crate::arch::interrupt::consts::{
     is_intr, is_page_fault, is_reserved_inst, is_syscall, is_timer_intr,
@@ -21,7 +21,7 @@ use crate::memory::{
 use crate::process::structs::ElfExt;
 use crate::sync::{EventBus, SpinLock, SpinNoIrqLock as Mutex};
 use crate::{
-    signal::{handle_signal, Siginfo, Signal, SignalAction, SignalStack, Sigset},
+    signal::{force_signal, handle_signal, Siginfo, Signal, SignalAction, SignalStack, Sigset},
     syscall::handle_syscall,
 };
 use alloc::{
@@ -112,13 +112,13 @@ impl Thread {
     }
 
     /// Construct virtual memory of a new user process from ELF at `inode`.
-    /// Return `(MemorySet, entry_point, ustack_top)`
+    /// Return `(entry_point, ustack_top, brk)`
     pub fn new_user_vm(
         inode: &Arc<dyn INode>,
         args: Vec<String>,
         envs: Vec<String>,
         vm: &mut MemorySet,
-    ) -> Result<(usize, usize), &'static str> {
+    ) -> Result<(usize, usize, usize), &'static str> {
         // Read ELF header
         // 0x3c0: magic number from ld-musl.so
         let mut data = [0u8; 0x3c0];
@@ -166,6 +166,8 @@ impl Thread {
         // Make page table
         vm.clear();
         let bias = elf.make_memory_set(vm, inode);
+        // the heap starts right after the loaded images
+        let mut brk = bias;
 
         // Check interpreter (for dynamic link)
         // When interpreter is used, map both dynamic linker and executable
@@ -181,7 +183,7 @@ impl Thread {
                 .read_at(0, &mut interp_data)
                 .map_err(|_| "failed to read from INode")?;
             let elf_interp = ElfFile::new(&interp_data)?;
-            elf_interp.append_as_interpreter(&interp_inode, vm, bias);
+            brk = elf_interp.append_as_interpreter(&interp_inode, vm, bias);
 
             // update auxiliary vector
             auxv.insert(abi::AT_ENTRY, elf.header.pt2.entry_point() as usize);
@@ -224,7 +226,7 @@ impl Thread {
             vm.with(|| ustack_top = init_info.push_at(ustack_top));
         }
 
-        Ok((entry_addr, ustack_top))
+        Ok((entry_addr, ustack_top, brk))
     }
 
     /// Make a new user process from ELF `data`
@@ -236,7 +238,8 @@ impl Thread {
     ) -> Arc<Thread> {
         // get virtual memory info
         let mut vm = MemorySet::new();
-        let (entry_addr, ustack_top) = Self::new_user_vm(inode, args, envs, &mut vm).unwrap();
+        let (entry_addr, ustack_top, brk) =
+            Self::new_user_vm(inode, args, envs, &mut vm).unwrap();
 
         let vm_token = vm.token();
         let vm = Arc::new(Mutex::new(vm));
@@ -335,7 +338,18 @@ impl Thread {
                 vm,
                 files,
                 cwd: String::from("/"),
+                umask: 0o022,
+                uid: 0,
+                euid: 0,
+                suid: 0,
+                gid: 0,
+                egid: 0,
+                sgid: 0,
+                file_limit: DEFAULT_FILE_LIMIT,
+                file_limit_max: DEFAULT_FILE_LIMIT,
                 exec_path: String::from(exec_path),
+                brk_start: brk,
+                brk,
                 futexes: BTreeMap::default(),
                 semaphores: SemProc::default(),
                 pid: Pid::new(), // allocated later
@@ -344,6 +358,7 @@ impl Thread {
                 children: Vec::new(),
                 threads: Vec::new(),
                 exit_code: 0,
+                exit_signal: 0,
                 pending_sigset: Sigset::empty(),
                 sig_queue: VecDeque::new(),
                 dispositions: [SignalAction::default(); Signal::RTMAX + 1],
@@ -356,6 +371,7 @@ impl Thread {
 
         // set pid to tid
         add_to_process_table(res.proc.clone(), Pid(res.tid));
+        res.proc.lock().threads.push(res.tid);
 
         res
     }
@@ -363,7 +379,7 @@ impl Thread {
     /// Fork a new process from current one
     /// Only current process is persisted
     pub fn fork(&self, tf: &UserContext) -> Arc<Thread> {
-        // clone virtual memory
+        // clone virtual memory, private pages are copied on write
         let vm = self.vm.lock().clone();
         let vm_token = vm.token();
         let vm = Arc::new(Mutex::new(vm));
@@ -378,7 +394,18 @@ impl Thread {
             vm: vm.clone(),
             files: proc.files.clone(), // share open file descriptions
             cwd: proc.cwd.clone(),
+            umask: proc.umask,
+            uid: proc.uid,
+            euid: proc.euid,
+            suid: proc.suid,
+            gid: proc.gid,
+            egid: proc.egid,
+            sgid: proc.sgid,
+            file_limit: proc.file_limit,
+            file_limit_max: proc.file_limit_max,
             exec_path: proc.exec_path.clone(),
+            brk_start: proc.brk_start,
+            brk: proc.brk,
             futexes: BTreeMap::default(),
             semaphores: proc.semaphores.clone(),
             pid: Pid::new(), // assigned later
@@ -387,6 +414,7 @@ impl Thread {
             children: Vec::new(),
             threads: Vec::new(),
             exit_code: 0,
+            exit_signal: 0,
             pending_sigset: Sigset::empty(),
             sig_queue: VecDeque::new(),
             dispositions: proc.dispositions.clone(),
@@ -519,8 +547,8 @@ pub fn spawn(thread: Arc<Thread>) {
                     info!("page fault from user @ {:#x}", addr);
 
                     if !handle_user_page_fault(&thread, addr) {
-                        // TODO: SIGSEGV
-                        panic!("page fault handle failed");
+                        info!("unhandled page fault @ {:#x}, raising SIGSEGV", addr);
+                        force_signal(&thread, Signal::SIGSEGV);
                     }
                 }
                 _ if is_syscall(trap_num) => exit = handle_syscall(&thread, cx).await,
@@ -554,6 +582,11 @@ pub fn spawn(thread: Arc<Thread>) {
                 exit = handle_signal(&thread, cx);
             }
 
+            // another thread called exit_group or exec
+            if !exit && !thread.proc.lock().threads.contains(&thread.tid) {
+                exit = true;
+            }
+
             thread.end_running(thread_context);
             if exit {
                 info!("thread {} stopped", thread.tid);
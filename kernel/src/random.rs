@@ -0,0 +1,100 @@
+//! Kernel entropy pool, with a ChaCha20 generator on top
+//!
+//! Timer readings taken on interrupts are folded into the key,
+//! and the key is replaced after every request, so earlier output
+//! can't be recovered from the state.
+
+use crate::arch::timer::timer_now;
+use crate::sync::SpinNoIrqLock as Mutex;
+
+struct Pool {
+    key: [u32; 8],
+    counter: u64,
+    /// where the next sample is mixed in
+    mix_index: usize,
+}
+
+static POOL: Mutex<Pool> = Mutex::new(Pool {
+    key: [0; 8],
+    counter: 0,
+    mix_index: 0,
+});
+
+impl Pool {
+    fn mix(&mut self, sample: u64) {
+        let i = self.mix_index;
+        self.key[i] = self.key[i].rotate_left(7) ^ sample as u32;
+        self.key[i + 1] = self.key[i + 1].rotate_left(7) ^ (sample >> 32) as u32;
+        self.mix_index = (i + 2) % self.key.len();
+    }
+
+    fn next_block(&mut self) -> [u32; 16] {
+        let block = chacha20_block(&self.key, self.counter);
+        self.counter = self.counter.wrapping_add(1);
+        block
+    }
+}
+
+/// Mix a sample that is hard to predict, like the time of an interrupt
+pub fn add_entropy(sample: u64) {
+    POOL.lock().mix(sample);
+}
+
+/// Bytes generated per lock of the pool, which keeps interrupts off
+const CHUNK_SIZE: usize = 256;
+
+/// Fill `buf` with random bytes
+pub fn get_random_bytes(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(CHUNK_SIZE) {
+        fill_chunk(chunk);
+    }
+}
+
+fn fill_chunk(buf: &mut [u8]) {
+    let mut pool = POOL.lock();
+    pool.mix(timer_now().as_nanos() as u64);
+    for chunk in buf.chunks_mut(64) {
+        let block = pool.next_block();
+        for (i, byte) in chunk.iter_mut().enumerate() {
+            *byte = (block[i / 4] >> (i % 4 * 8)) as u8;
+        }
+    }
+    // fast key erasure
+    let block = pool.next_block();
+    pool.key.copy_from_slice(&block[..8]);
+}
+
+fn chacha20_block(key: &[u32; 8], counter: u64) -> [u32; 16] {
+    let mut state = [0u32; 16];
+    // "expand 32-byte k"
+    state[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+    for (x, s) in x.iter_mut().zip(state.iter()) {
+        *x = x.wrapping_add(*s);
+    }
+    x
+}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
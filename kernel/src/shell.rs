@@ -22,8 +22,18 @@ pub fn add_user_shell() {
     #[cfg(not(target_arch = "x86_64"))]
     let init_envs = Vec::new();
 
+    #[cfg(not(feature = "run_cmdline"))]
     let init_args: Vec<String> = vec!["busybox".into(), "ash".into()];
 
+    // run the INIT given at build time as a shell command, for automatic testing
+    #[cfg(feature = "run_cmdline")]
+    let init_args: Vec<String> = vec![
+        "busybox".into(),
+        "sh".into(),
+        "-c".into(),
+        env!("INIT").into(),
+    ];
+
     if let Ok(inode) = ROOT_INODE.lookup(init_shell) {
         let thread = Thread::new_user(&inode, init_shell, init_args, init_envs);
         spawn(thread);
@@ -107,6 +107,27 @@ pub fn send_signal(process: Arc<Mutex<Process>>, tid: isize, info: Siginfo) {
     )
 }
 
+/// Send a signal raised by the thread itself, e.g. on a bad memory access.
+/// Like linux, it is neither blocked nor ignored, as the thread would only fault again
+pub fn force_signal(thread: &Arc<Thread>, signal: Signal) {
+    thread.inner.lock().sig_mask.remove(signal);
+    let mut process = thread.proc.lock();
+    if process.dispositions[signal as usize].handler == SIG_IGN {
+        process.dispositions[signal as usize].handler = SIG_DFL;
+    }
+    drop(process);
+    send_signal(
+        thread.proc.clone(),
+        thread.tid as isize,
+        Siginfo {
+            signo: signal as i32,
+            errno: 0,
+            code: SI_KERNEL,
+            field: Default::default(),
+        },
+    );
+}
+
 /// See musl struct __ucontext
 /// Not exactly the same for now
 #[repr(C)]
@@ -167,16 +188,21 @@ pub fn handle_signal(thread: &Arc<Thread>, tf: &mut UserContext) -> bool {
 
         // enter signal handler
         match action.handler {
-            // TODO: complete default actions
             x if x == SIG_DFL => {
                 match signal {
-                    SIGALRM | SIGHUP | SIGINT => {
+                    SIGCHLD | SIGURG | SIGWINCH => {
+                        info!("default action: Ign");
+                    }
+                    SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU | SIGCONT => {
+                        // TODO: job control
+                        warn!("default action: Stop/Cont, not supported");
+                    }
+                    _ => {
+                        // Term and Core alike, no core dumps here
                         info!("default action: Term");
-                        // TODO: exit code ref please?
-                        process.exit(info.signo as usize + 128);
+                        process.exit_by_signal(info.signo as usize);
                         return true;
                     }
-                    _ => (),
                 }
             }
             x if x == SIG_IGN => {
@@ -195,9 +221,12 @@ pub fn handle_signal(thread: &Arc<Thread>, tf: &mut UserContext) -> bool {
                 let sig_mask = inner.sig_mask;
 
                 // update sig mask (see man sigaction(2))
-                // 1. block current
+                // 1. block current, unless SA_NODEFER
                 // 2. block mask in disposition
-                inner.sig_mask.add(signal);
+                // the old mask is restored by sigreturn
+                if !action_flags.contains(SignalActionFlags::NODEFER) {
+                    inner.sig_mask.add(signal);
+                }
                 inner.sig_mask.add_set(&action.mask);
 
                 // save original signal alternate stack
@@ -238,7 +267,10 @@ pub fn handle_signal(thread: &Arc<Thread>, tf: &mut UserContext) -> bool {
                 } {
                     frame
                 } else {
-                    unimplemented!()
+                    // no room for the frame, the handler can't run
+                    warn!("bad signal stack at {:#x}, killing the process", sig_sp);
+                    process.exit_by_signal(SIGSEGV as usize);
+                    return true;
                 };
                 frame.info = info;
                 frame.ucontext = SignalUserContext {
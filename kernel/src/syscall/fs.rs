@@ -2,7 +2,6 @@
 
 #![allow(dead_code)]
 
-use core::cell::UnsafeCell;
 use core::cmp::min;
 use core::mem::size_of;
 #[cfg(not(target_arch = "mips"))]
@@ -12,7 +11,9 @@ use crate::drivers::SOCKET_ACTIVITY;
 use crate::fs::*;
 use crate::memory::MemorySet;
 use crate::sync::Condvar;
-use crate::trap::TICK_ACTIVITY;
+use crate::arch::timer::timer_now;
+use crate::trap::{NAIVE_TIMER, TICK_ACTIVITY};
+use core::time::Duration;
 use alloc::boxed::Box;
 use core::future::Future;
 use core::pin::Pin;
@@ -22,11 +23,13 @@ use bitvec::prelude::{BitSlice, BitVec, Lsb0};
 
 use super::*;
 use crate::fs::epoll::EpollInstance;
-use crate::fs::fcntl::{FD_CLOEXEC, F_SETFD, O_CLOEXEC, O_NONBLOCK};
+use crate::fs::fcntl::{FD_CLOEXEC, F_GETFL, F_SETFD, F_SETFL, O_CLOEXEC, O_NONBLOCK};
 use crate::fs::FileLike;
 use crate::process::Process;
 use crate::syscall::SysError::{EINTR, EINVAL, ESPIPE};
-use rcore_fs::vfs::PollStatus;
+use rcore_fs::vfs::{FileSystem, PollStatus};
+use rcore_fs_mountfs::MNode;
+use rcore_fs_ramfs::RamFS;
 
 impl Syscall<'_> {
     pub async fn sys_read(&mut self, fd: usize, base: UserOutPtr<u8>, len: usize) -> SysResult {
@@ -37,20 +40,23 @@ impl Syscall<'_> {
         }
         let slice = unsafe { self.vm().check_write_array(base.ptr(), len)? };
 
-        let file_like = proc.get_file_like(fd)?;
+        // don't hold the process lock while blocking on the file
+        let mut file_like = proc.get_file_like(fd)?.clone();
+        drop(proc);
         let len = file_like.read(slice).await?;
         Ok(len)
     }
 
-    pub fn sys_write(&mut self, fd: usize, base: *const u8, len: usize) -> SysResult {
+    pub async fn sys_write(&mut self, fd: usize, base: *const u8, len: usize) -> SysResult {
         let mut proc = self.process();
         if !proc.pid.is_init() {
             //we trust pid 0 process
             info!("write: fd: {}, base: {:?}, len: {:#x}", fd, base, len);
         }
         let slice = unsafe { self.vm().check_read_array(base, len)? };
-        let file_like = proc.get_file_like(fd)?;
-        let len = file_like.write(slice)?;
+        let mut file_like = proc.get_file_like(fd)?.clone();
+        drop(proc);
+        let len = file_like.write(slice).await?;
         Ok(len)
     }
 
@@ -65,9 +71,14 @@ impl Syscall<'_> {
             "pread: fd: {}, base: {:?}, len: {}, offset: {}",
             fd, base, len, offset
         );
+        if (offset as isize) < 0 {
+            return Err(EINVAL);
+        }
         let mut proc = self.process();
         let slice = unsafe { self.vm().check_write_array(base.ptr(), len)? };
-        let len = proc.get_file(fd)?.read_at(offset, slice).await?;
+        let file = proc.get_seekable_file(fd)?.clone();
+        drop(proc);
+        let len = file.read_at(offset, slice).await?;
         Ok(len)
     }
 
@@ -82,9 +93,12 @@ impl Syscall<'_> {
             "pwrite: fd: {}, base: {:?}, len: {}, offset: {}",
             fd, base, len, offset
         );
+        if (offset as isize) < 0 {
+            return Err(EINVAL);
+        }
         let mut proc = self.process();
         let slice = unsafe { self.vm().check_read_array(base, len)? };
-        let len = proc.get_file(fd)?.write_at(offset, slice)?;
+        let len = proc.get_seekable_file(fd)?.write_at(offset, slice)?;
         Ok(len)
     }
 
@@ -132,7 +146,7 @@ impl Syscall<'_> {
         // check whether the fds is valid and is owned by this process
         let condvars = alloc::vec![&(*TICK_ACTIVITY), &(*SOCKET_ACTIVITY)];
 
-        let mut polls = ufds.read_array(nfds).unwrap();
+        let mut polls = ufds.read_array(nfds)?;
 
         if !proc.pid.is_init() {
             info!("poll: fds: {:?}", polls);
@@ -140,10 +154,18 @@ impl Syscall<'_> {
 
         drop(proc);
 
+        // a negative timeout means infinity
+        let deadline = match timeout_msecs as i32 {
+            t if t < 0 => None,
+            t => Some(timer_now() + Duration::from_millis(t as u64)),
+        };
+
         #[must_use = "future does nothing unless polled/`await`-ed"]
         struct PollFuture<'a> {
             polls: &'a mut Vec<PollFd>,
             syscall: &'a Syscall<'a>,
+            deadline: Option<Duration>,
+            timer_added: bool,
         }
 
         impl<'a> Future for PollFuture<'a> {
@@ -157,6 +179,10 @@ impl Syscall<'_> {
                 // iterate each poll to check whether it is ready
                 for poll in self.as_mut().polls.iter_mut() {
                     poll.revents = PE::empty();
+                    // negative fds are ignored
+                    if (poll.fd as i32) < 0 {
+                        continue;
+                    }
                     if let Some(file_like) = proc.files.get(&(poll.fd as usize)) {
                         let mut fut = Box::pin(file_like.async_poll());
                         let status = match fut.as_mut().poll(cx) {
@@ -166,18 +192,18 @@ impl Syscall<'_> {
                         };
                         if status.error {
                             poll.revents |= PE::HUP;
-                            events += 1;
                         }
                         if status.read && poll.events.contains(PE::IN) {
                             poll.revents |= PE::IN;
-                            events += 1;
                         }
                         if status.write && poll.events.contains(PE::OUT) {
                             poll.revents |= PE::OUT;
-                            events += 1;
                         }
                     } else {
-                        poll.revents |= PE::ERR;
+                        poll.revents |= PE::INVAL;
+                    }
+                    // count fds, not events
+                    if !poll.revents.is_empty() {
                         events += 1;
                     }
                 }
@@ -188,6 +214,22 @@ impl Syscall<'_> {
                     return Poll::Ready(Ok(events));
                 }
 
+                if let Some(deadline) = self.deadline {
+                    if timer_now() >= deadline {
+                        return Poll::Ready(Ok(0));
+                    }
+                    if !self.timer_added {
+                        let waker = cx.waker().clone();
+                        NAIVE_TIMER
+                            .lock()
+                            .add(deadline, Box::new(move |_| waker.wake()));
+                        self.timer_added = true;
+                    }
+                }
+                if self.syscall.thread.has_signal_to_handle() {
+                    return Poll::Ready(Err(EINTR));
+                }
+
                 return Poll::Pending;
             }
         }
@@ -195,6 +237,8 @@ impl Syscall<'_> {
         let future = PollFuture {
             polls: &mut polls,
             syscall: self,
+            deadline,
+            timer_added: false,
         };
         let res = future.await;
         ufds.write_array(&polls)?;
@@ -207,10 +251,17 @@ impl Syscall<'_> {
         read: *mut u32,
         write: *mut u32,
         err: *mut u32,
-        timeout: *const TimeVal,
+        timeout: *const TimeSpec,
         _sigset: *const u32,
     ) -> SysResult {
-        self.sys_select(nfds, read, write, err, timeout)
+        // unlike select, the timeout is a timespec and is left untouched
+        let timeout_msecs = if !timeout.is_null() {
+            let timeout = unsafe { self.vm().check_read_ptr(timeout)? };
+            Some(timeout.to_msec())
+        } else {
+            None
+        };
+        self.select(nfds, read, write, err, timeout_msecs).0
     }
 
     pub fn sys_select(
@@ -219,26 +270,58 @@ impl Syscall<'_> {
         read: *mut u32,
         write: *mut u32,
         err: *mut u32,
-        timeout: *const TimeVal,
+        timeout: *mut TimeVal,
     ) -> SysResult {
+        let timeout = if !timeout.is_null() {
+            Some(unsafe { self.vm().check_write_ptr(timeout)? })
+        } else {
+            None
+        };
+        let timeout_msecs = timeout.as_ref().map(|timeout| timeout.to_msec());
+        let (res, remaining_msecs) = self.select(nfds, read, write, err, timeout_msecs);
+        // like linux, report the time left
+        if let Some(timeout) = timeout {
+            *timeout = TimeVal {
+                sec: (remaining_msecs / 1000) as usize,
+                usec: (remaining_msecs % 1000 * 1000) as usize,
+            };
+        }
+        res
+    }
+
+    /// Wait until one of the fds is ready, or `timeout_msecs` passed.
+    /// Return the result and the time left.
+    fn select(
+        &mut self,
+        nfds: usize,
+        read: *mut u32,
+        write: *mut u32,
+        err: *mut u32,
+        timeout_msecs: Option<u64>,
+    ) -> (SysResult, u64) {
         info!(
             "select: nfds: {}, read: {:?}, write: {:?}, err: {:?}, timeout: {:?}",
-            nfds, read, write, err, timeout
+            nfds, read, write, err, timeout_msecs
         );
-        if nfds as u64 == 0 {
-            return Ok(0);
-        }
         let proc = self.process();
-        let mut read_fds = FdSet::new(&self.vm(), read, nfds)?;
-        let mut write_fds = FdSet::new(&self.vm(), write, nfds)?;
-        let mut err_fds = FdSet::new(&self.vm(), err, nfds)?;
-        let timeout_msecs = if !timeout.is_null() {
-            let timeout = unsafe { self.vm().check_read_ptr(timeout)? };
-            timeout.to_msec()
-        } else {
-            // infinity
-            1 << 31
+        let fd_sets = FdSet::new(&self.vm(), read, nfds).and_then(|read_fds| {
+            let write_fds = FdSet::new(&self.vm(), write, nfds)?;
+            let err_fds = FdSet::new(&self.vm(), err, nfds)?;
+            Ok((read_fds, write_fds, err_fds))
+        });
+        let (mut read_fds, mut write_fds, mut err_fds) = match fd_sets {
+            Ok(fd_sets) => fd_sets,
+            Err(err) => return (Err(err), 0),
         };
+        // every fd asked for must be open
+        for fd in 0..nfds {
+            let asked = read_fds.contains(fd) || write_fds.contains(fd) || err_fds.contains(fd);
+            if asked && !proc.files.contains_key(&fd) {
+                return (Err(SysError::EBADF), 0);
+            }
+        }
+        // infinity
+        let timeout_msecs = timeout_msecs.unwrap_or(1 << 31);
 
         let condvars = alloc::vec![&(*TICK_ACTIVITY), &(*SOCKET_ACTIVITY)];
 
@@ -249,13 +332,14 @@ impl Syscall<'_> {
         drop(proc);
 
         let begin_time_ms = crate::trap::uptime_msec();
-        Condvar::wait_events(condvars.as_slice(), move || {
+        let res = Condvar::wait_events(condvars.as_slice(), || {
             let proc = self.process();
             let mut events = 0;
             for (&fd, file_like) in proc.files.iter() {
-                //                if fd >= nfds {
-                //                    continue;
-                //                }
+                // fds from nfds on are ignored
+                if fd >= nfds {
+                    continue;
+                }
                 if !err_fds.contains(fd) && !read_fds.contains(fd) && !write_fds.contains(fd) {
                     continue;
                 }
@@ -295,7 +379,9 @@ impl Syscall<'_> {
             }
 
             return None;
-        })
+        });
+        let elapsed_msecs = (crate::trap::uptime_msec() - begin_time_ms) as u64;
+        (res, timeout_msecs.saturating_sub(elapsed_msecs))
     }
 
     pub fn sys_epoll_create(&mut self, size: usize) -> SysResult {
@@ -311,7 +397,7 @@ impl Syscall<'_> {
         info!("epoll_create1: flags: {:?}", flags);
         let mut proc = self.process();
         let epoll_instance = EpollInstance::new(flags);
-        let fd = proc.add_file(FileLike::EpollInstance(epoll_instance));
+        let fd = proc.add_file(FileLike::EpollInstance(epoll_instance))?;
         Ok(fd)
     }
 
@@ -542,7 +628,8 @@ impl Syscall<'_> {
             unsafe { IoVecs::check_and_new(iov_ptr.ptr(), iov_count, &self.vm(), true)? };
 
         // read all data to a buf
-        let file_like = proc.get_file_like(fd)?;
+        let mut file_like = proc.get_file_like(fd)?.clone();
+        drop(proc);
         let mut buf = iovs.new_buf(true);
         let len = file_like.read(buf.as_mut_slice()).await?;
         // copy data to user
@@ -550,7 +637,12 @@ impl Syscall<'_> {
         Ok(len)
     }
 
-    pub fn sys_writev(&mut self, fd: usize, iov_ptr: *const IoVec, iov_count: usize) -> SysResult {
+    pub async fn sys_writev(
+        &mut self,
+        fd: usize,
+        iov_ptr: *const IoVec,
+        iov_count: usize,
+    ) -> SysResult {
         let mut proc = self.process();
         if !proc.pid.is_init() {
             // we trust pid 0 process
@@ -567,16 +659,17 @@ impl Syscall<'_> {
         let iovs = unsafe { IoVecs::check_and_new(iov_ptr, iov_count, &self.vm(), false)? };
 
         let buf = iovs.read_all_to_vec();
-        let file_like = proc.get_file_like(fd)?;
-        let len = file_like.write(buf.as_slice())?;
+        let mut file_like = proc.get_file_like(fd)?.clone();
+        drop(proc);
+        let len = file_like.write(buf.as_slice()).await?;
         Ok(len)
     }
 
-    pub fn sys_open(&mut self, path: *const u8, flags: usize, mode: usize) -> SysResult {
-        self.sys_openat(AT_FDCWD, path, flags, mode)
+    pub async fn sys_open(&mut self, path: *const u8, flags: usize, mode: usize) -> SysResult {
+        self.sys_openat(AT_FDCWD, path, flags, mode).await
     }
 
-    pub fn sys_openat(
+    pub async fn sys_openat(
         &mut self,
         dir_fd: usize,
         path: *const u8,
@@ -591,34 +684,109 @@ impl Syscall<'_> {
             dir_fd as isize, path, flags, mode
         );
 
+        // a file created by this very open is accessible whatever its mode
+        let mut created = false;
         let inode = if flags.contains(OpenFlags::CREATE) {
-            let (dir_path, file_name) = split_path(&path);
-            // relative to cwd
-            let dir_inode = proc.lookup_inode_at(dir_fd, dir_path, true)?;
+            let (dir_inode, file_name) = proc.lookup_parent_at(dir_fd, &path)?;
             match dir_inode.find(file_name) {
                 Ok(file_inode) => {
                     if flags.contains(OpenFlags::EXCLUSIVE) {
                         return Err(SysError::EEXIST);
                     }
-                    if flags.contains(OpenFlags::TRUNCATE) {
-                        if let Err(e) = file_inode.resize(0) {
-                            // TODO: do something? what about device file?
+                    if file_inode.metadata()?.type_ == FileType::SymLink {
+                        if flags.contains(OpenFlags::NOFOLLOW) {
+                            return Err(SysError::ELOOP);
                         }
+                        proc.lookup_inode_at(dir_fd, &path, true)?
+                    } else {
+                        file_inode
                     }
-                    file_inode
                 }
                 Err(FsError::EntryNotFound) => {
+                    check_access(&dir_inode, W_OK | X_OK, proc.euid, proc.egid)?;
+                    let mode = mode & !proc.umask & 0o7777;
                     let inode = dir_inode.create(file_name, FileType::File, mode as u32)?;
+                    set_creator(&inode, &proc);
                     TimeSpec::update(&inode);
-                    TimeSpec::update(&dir_inode);
+                    TimeSpec::update_modified(&dir_inode);
+                    created = true;
                     inode
                 }
                 Err(e) => return Err(SysError::from(e)),
             }
         } else {
-            proc.lookup_inode_at(dir_fd, &path, true)?
+            let follow = !flags.contains(OpenFlags::NOFOLLOW);
+            let inode = proc.lookup_inode_at(dir_fd, &path, follow)?;
+            if !follow && inode.metadata()?.type_ == FileType::SymLink {
+                return Err(SysError::ELOOP);
+            }
+            inode
         };
 
+        if !created {
+            let mut access = 0;
+            if flags.readable() {
+                access |= R_OK;
+            }
+            if flags.writable() || flags.contains(OpenFlags::TRUNCATE) {
+                access |= W_OK;
+            }
+            check_access(&inode, access, proc.euid, proc.egid)?;
+        }
+
+        // a FIFO opens an end of the pipe shared by everyone who opened it
+        let metadata = inode.metadata()?;
+        if metadata.type_ == FileType::NamedPipe {
+            let direction = match (flags.readable(), flags.writable()) {
+                (true, false) => PipeEnd::Read,
+                (false, true) => PipeEnd::Write,
+                // like linux, O_RDWR holds both ends and never waits
+                (true, true) => PipeEnd::Both,
+                _ => return Err(SysError::EINVAL),
+            };
+            let write = direction == PipeEnd::Write;
+            let pipe = Arc::new(Pipe::open_fifo((metadata.dev, metadata.inode), direction));
+            if flags.contains(OpenFlags::NONBLOCK) {
+                // like linux, refuse a non-blocking writer without any reader
+                if write && !pipe.has_peer() {
+                    return Err(SysError::ENXIO);
+                }
+            } else if !pipe.has_peer() {
+                // a blocking open waits for the other end to be opened
+                drop(proc);
+                pipe.wait_for_peer().await;
+                proc = self.process();
+            }
+            let file = FileHandle::new(
+                pipe,
+                flags.to_options(),
+                String::from(path),
+                true,
+                flags.contains(OpenFlags::CLOEXEC),
+            );
+            let fd = proc.add_file(FileLike::File(file))?;
+            return Ok(fd);
+        }
+
+        if flags.writable() || flags.contains(OpenFlags::TRUNCATE) {
+            match inode.metadata()?.type_ {
+                FileType::Dir => return Err(SysError::EISDIR),
+                FileType::File if flags.writable() && flags.contains(OpenFlags::TRUNCATE) => {
+                    inode.resize(0)?;
+                    TimeSpec::update_modified(&inode);
+                }
+                // truncating a device is a no-op
+                _ => {}
+            }
+        }
+
+        // a device node made by mknod is served by the driver of its number
+        let inode = match metadata.type_ {
+            FileType::CharDevice | FileType::BlockDevice => {
+                device_by_rdev(metadata.rdev).unwrap_or(inode)
+            }
+            _ => inode,
+        };
         let file = FileHandle::new(
             inode,
             flags.to_options(),
@@ -632,7 +800,7 @@ impl Syscall<'_> {
             debug!("files before open {:#?}", proc.files);
         }
 
-        let fd = proc.add_file(FileLike::File(file));
+        let fd = proc.add_file(FileLike::File(file))?;
         Ok(fd)
     }
 
@@ -660,10 +828,9 @@ impl Syscall<'_> {
         mode: usize,
         flags: usize,
     ) -> SysResult {
-        // TODO: check permissions based on uid/gid
         let proc = self.process();
         let path = check_and_clone_cstr(path)?;
-        let flags = AtFlags::from_bits_truncate(flags);
+        let flags = AtFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
         if !proc.pid.is_init() {
             // we trust pid 0 process
             info!(
@@ -671,8 +838,18 @@ impl Syscall<'_> {
                 dirfd as isize, path, mode, flags
             );
         }
-        let _inode =
+        if mode & !(R_OK | W_OK | X_OK) != 0 || flags.contains(AtFlags::EMPTY_PATH) {
+            return Err(SysError::EINVAL);
+        }
+        let inode =
             proc.lookup_inode_at(dirfd, &path, !flags.contains(AtFlags::SYMLINK_NOFOLLOW))?;
+        // F_OK (0) only checks existence
+        // the real ids are checked, unless AT_EACCESS asks for the effective ones
+        if flags.contains(AtFlags::EACCESS) {
+            check_access(&inode, mode, proc.euid, proc.egid)?;
+        } else {
+            check_access(&inode, mode, proc.uid, proc.gid)?;
+        }
         Ok(0)
     }
 
@@ -721,6 +898,10 @@ impl Syscall<'_> {
             dirfd as isize, path, stat_ptr, flags
         );
 
+        // an empty path refers to dirfd itself, but only with AT_EMPTY_PATH
+        if path.is_empty() && !flags.contains(AtFlags::EMPTY_PATH) {
+            return Err(SysError::ENOENT);
+        }
         let inode =
             proc.lookup_inode_at(dirfd, &path, !flags.contains(AtFlags::SYMLINK_NOFOLLOW))?;
         let stat = Stat::from(inode.metadata()?);
@@ -732,6 +913,25 @@ impl Syscall<'_> {
         self.sys_fstatat(AT_FDCWD, path, stat_ptr, 0)
     }
 
+    pub fn sys_statfs(&mut self, path: *const u8, buf: *mut StatFs) -> SysResult {
+        let proc = self.process();
+        let path = check_and_clone_cstr(path)?;
+        info!("statfs: path: {:?}, buf: {:?}", path, buf);
+        let buf = unsafe { self.vm().check_write_ptr(buf)? };
+        let inode = proc.lookup_inode(&path)?;
+        *buf = StatFs::of(&inode)?;
+        Ok(0)
+    }
+
+    pub fn sys_fstatfs(&mut self, fd: usize, buf: *mut StatFs) -> SysResult {
+        info!("fstatfs: fd: {}, buf: {:?}", fd, buf);
+        let mut proc = self.process();
+        let buf = unsafe { self.vm().check_write_ptr(buf)? };
+        let inode = proc.get_file(fd)?.inode();
+        *buf = StatFs::of(&inode)?;
+        Ok(0)
+    }
+
     pub fn sys_readlink(&mut self, path: *const u8, base: *mut u8, len: usize) -> SysResult {
         self.sys_readlinkat(AT_FDCWD, path, base, len)
     }
@@ -751,10 +951,15 @@ impl Syscall<'_> {
             dirfd as isize, path, base, len
         );
 
+        if len == 0 {
+            return Err(SysError::EINVAL);
+        }
         let inode = proc.lookup_inode_at(dirfd, &path, false)?;
         if inode.metadata()?.type_ == FileType::SymLink {
-            // TODO: recursive link resolution and loop detection
-            let len = inode.read_at(0, slice)?;
+            // the target is truncated to len, and not NUL-terminated
+            let target = proc.read_link(&inode)?;
+            let len = min(target.len(), slice.len());
+            slice[..len].copy_from_slice(&target.as_bytes()[..len]);
             Ok(len)
         } else {
             Err(SysError::EINVAL)
@@ -771,13 +976,8 @@ impl Syscall<'_> {
         info!("lseek: fd: {}, pos: {:?}", fd, pos);
 
         let mut proc = self.process();
-        let file = proc.get_file(fd)?;
-        if file.pipe {
-            Err(ESPIPE)
-        } else {
-            let offset = file.seek(pos)?;
-            Ok(offset as usize)
-        }
+        let offset = proc.get_seekable_file(fd)?.seek(pos)?;
+        Ok(offset as usize)
     }
 
     pub fn sys_fsync(&mut self, fd: usize) -> SysResult {
@@ -786,7 +986,7 @@ impl Syscall<'_> {
         Ok(0)
     }
 
-    pub fn sys_flock(&mut self, fd: usize, operation: usize) -> SysResult {
+    pub async fn sys_flock(&mut self, fd: usize, operation: usize) -> SysResult {
         bitflags! {
             struct Operation: u8 {
                 const LOCK_SH = 1;
@@ -795,12 +995,18 @@ impl Syscall<'_> {
                 const LOCK_UN = 8;
             }
         }
-        let operation = Operation::from_bits(operation as u8).unwrap();
+        let operation = Operation::from_bits(operation as u8).ok_or(SysError::EINVAL)?;
         info!("flock: fd: {}, operation: {:?}", fd, operation);
-        let mut proc = self.process();
-        // let file_like = proc.get_file_like(fd)?;
-        let file = proc.get_file(fd)?;
-        Ok(0)
+        let kind = match operation - Operation::LOCK_NB {
+            Operation::LOCK_SH => Some(Flock::Shared),
+            Operation::LOCK_EX => Some(Flock::Exclusive),
+            Operation::LOCK_UN => None,
+            _ => return Err(SysError::EINVAL),
+        };
+        // don't hold the process lock while waiting for the file lock
+        let file = self.process().get_file(fd)?.clone();
+        file.flock(kind, operation.contains(Operation::LOCK_NB))
+            .await
     }
 
     pub fn sys_fdatasync(&mut self, fd: usize) -> SysResult {
@@ -813,12 +1019,25 @@ impl Syscall<'_> {
         let proc = self.process();
         let path = check_and_clone_cstr(path)?;
         info!("truncate: path: {:?}, len: {}", path, len);
-        proc.lookup_inode(&path)?.resize(len)?;
+        if (len as isize) < 0 {
+            return Err(SysError::EINVAL);
+        }
+        let inode = proc.lookup_inode(&path)?;
+        if inode.metadata()?.type_ == FileType::Dir {
+            return Err(SysError::EISDIR);
+        }
+        check_access(&inode, W_OK, proc.euid, proc.egid)?;
+        // growing zero-fills, shrinking drops the tail
+        inode.resize(len)?;
+        TimeSpec::update_modified(&inode);
         Ok(0)
     }
 
     pub fn sys_ftruncate(&mut self, fd: usize, len: usize) -> SysResult {
         info!("ftruncate: fd: {}, len: {}", fd, len);
+        if (len as isize) < 0 {
+            return Err(SysError::EINVAL);
+        }
         self.process().get_file(fd)?.set_len(len as u64)?;
         Ok(0)
     }
@@ -835,7 +1054,9 @@ impl Syscall<'_> {
         );
         let mut proc = self.process();
         let buf = unsafe { self.vm().check_write_array(buf as *mut u8, buf_size)? };
-        let file = proc.get_file(fd)?;
+        // don't hold the process lock, /proc may be listing this process
+        let mut file = proc.get_file(fd)?.clone();
+        drop(proc);
         let info = file.metadata()?;
         if info.type_ != FileType::Dir {
             return Err(SysError::ENOTDIR);
@@ -846,37 +1067,62 @@ impl Syscall<'_> {
                 Err(FsError::EntryNotFound) => break,
                 r => r,
             }?;
+            // d_off is where the next entry starts
+            let next_offset = file.seek(SeekFrom::Current(0))?;
             let ok = writer.try_write(
                 info.inode as u64,
+                next_offset,
                 DirentType::from_type(&info.type_).bits(),
                 &name,
             );
             if !ok {
                 file.seek(SeekFrom::Current(-1))?;
+                // not even one entry fits in the buffer
+                if writer.written_size == 0 {
+                    return Err(SysError::EINVAL);
+                }
                 break;
             }
         }
         Ok(writer.written_size)
     }
 
+    pub fn sys_dup(&mut self, fd: usize) -> SysResult {
+        info!("dup: {}", fd);
+        let mut proc = self.process();
+        // the new fd shares offset and flags with the old one
+        let file_like = proc.get_file_like(fd)?.dup(false);
+        proc.add_file(file_like)
+    }
+
     pub fn sys_dup2(&mut self, fd1: usize, fd2: usize) -> SysResult {
         info!("dup2: from {} to {}", fd1, fd2);
-        self.dup_impl(fd1, fd2, 0)
+        if fd1 == fd2 {
+            // nothing to do, but fd1 must be valid
+            self.process().get_file_like(fd1)?;
+            return Ok(fd2);
+        }
+        self.dup_impl(fd1, fd2, false)
     }
 
-    fn dup_impl(&mut self, fd1: usize, fd2: usize, flags: usize) -> SysResult {
+    /// the new fd never inherits FD_CLOEXEC, it is set only by `cloexec`
+    fn dup_impl(&mut self, fd1: usize, fd2: usize, cloexec: bool) -> SysResult {
         let mut proc = self.process();
-        // close fd2 first if it is opened
-        proc.files.remove(&fd2);
-
-        let mut file_like = proc.get_file_like(fd1)?.dup(flags != 0);
+        if fd2 >= proc.file_limit {
+            return Err(SysError::EBADF);
+        }
+        let file_like = proc.get_file_like(fd1)?.dup(cloexec);
+        // fd2 is closed if it is opened
         proc.files.insert(fd2, file_like);
         Ok(fd2)
     }
 
     pub fn sys_dup3(&mut self, fd1: usize, fd2: usize, flags: usize) -> SysResult {
         info!("dup3: from {} to {} with flags = {:#x}", fd1, fd2, flags);
-        self.dup_impl(fd1, fd2, flags)
+        if fd1 == fd2 || flags & !O_CLOEXEC != 0 {
+            return Err(SysError::EINVAL);
+        }
+        self.dup_impl(fd1, fd2, flags & O_CLOEXEC != 0)
     }
 
     pub fn sys_ioctl(
@@ -896,16 +1142,36 @@ impl Syscall<'_> {
             FIOCLEX => self.sys_fcntl(fd, F_SETFD, FD_CLOEXEC),
             FIONCLEX => self.sys_fcntl(fd, F_SETFD, 0),
             FIONBIO => {
-                let data = arg1 as *const i32;
-                let val = unsafe { *data };
-                if val == 0 {
-                    self.sys_fcntl(fd, F_SETFD, 0)
-                } else {
-                    self.sys_fcntl(fd, F_SETFD, O_NONBLOCK)
-                }
+                let nonblock = unsafe { *self.vm().check_read_ptr(arg1 as *const i32)? } != 0;
+                // only O_NONBLOCK changes, O_APPEND is kept
+                let flags = self.sys_fcntl(fd, F_GETFL, 0)? & !O_NONBLOCK;
+                let flags = if nonblock { flags | O_NONBLOCK } else { flags };
+                self.sys_fcntl(fd, F_SETFL, flags)
             }
             _ => {
                 let mut proc = self.process();
+                // the tty copies its structures from and to user memory directly
+                unsafe {
+                    let vm = self.vm();
+                    match request {
+                        TCGETS => {
+                            vm.check_write_ptr(arg1 as *mut Termios)?;
+                        }
+                        TCSETS | TCSETSW | TCSETSF => {
+                            vm.check_read_ptr(arg1 as *const Termios)?;
+                        }
+                        TIOCGWINSZ => {
+                            vm.check_write_ptr(arg1 as *mut Winsize)?;
+                        }
+                        TIOCGPGRP => {
+                            vm.check_write_ptr(arg1 as *mut i32)?;
+                        }
+                        TIOCSPGRP => {
+                            vm.check_read_ptr(arg1 as *const i32)?;
+                        }
+                        _ => {}
+                    }
+                }
                 let file_like = proc.get_file_like(fd)?;
                 file_like.ioctl(request, arg1, arg2, arg3)
             }
@@ -919,39 +1185,18 @@ impl Syscall<'_> {
             // we trust pid 0 process
             info!("chdir: path: {:?}", path);
         }
+        if path.is_empty() {
+            return Err(SysError::ENOENT);
+        }
 
         let inode = proc.lookup_inode(&path)?;
         let info = inode.metadata()?;
         if info.type_ != FileType::Dir {
             return Err(SysError::ENOTDIR);
         }
+        check_access(&inode, X_OK, proc.euid, proc.egid)?;
 
-        // BUGFIX: '..' and '.'
-        if path.len() > 0 {
-            let cwd = match path.as_bytes()[0] {
-                b'/' => String::from("/"),
-                _ => proc.cwd.clone(),
-            };
-            let mut cwd_vec: Vec<_> = cwd.split("/").filter(|&x| x != "").collect();
-            let path_split = path.split("/").filter(|&x| x != "");
-            for seg in path_split {
-                if seg == ".." {
-                    cwd_vec.pop();
-                } else if seg == "." {
-                    // nothing to do here.
-                } else {
-                    cwd_vec.push(seg);
-                }
-            }
-            proc.cwd = String::from("");
-            for seg in cwd_vec {
-                proc.cwd.push_str("/");
-                proc.cwd.push_str(seg);
-            }
-            if proc.cwd == "" {
-                proc.cwd = String::from("/");
-            }
-        }
+        proc.cwd = absolute_path(&proc.cwd, &path);
         Ok(0)
     }
 
@@ -974,11 +1219,129 @@ impl Syscall<'_> {
             olddirfd as isize, oldpath, newdirfd as isize, newpath
         );
 
-        let (old_dir_path, old_file_name) = split_path(&oldpath);
-        let (new_dir_path, new_file_name) = split_path(&newpath);
-        let old_dir_inode = proc.lookup_inode_at(olddirfd, old_dir_path, false)?;
-        let new_dir_inode = proc.lookup_inode_at(newdirfd, new_dir_path, false)?;
-        old_dir_inode.move_(old_file_name, &new_dir_inode, new_file_name)?;
+        let (old_dir_inode, old_file_name) = proc.lookup_parent_at(olddirfd, &oldpath)?;
+        let (new_dir_inode, new_file_name) = proc.lookup_parent_at(newdirfd, &newpath)?;
+        match old_file_name {
+            "" | "." | ".." => return Err(SysError::EINVAL),
+            _ => {}
+        }
+        let old_inode = old_dir_inode.find(old_file_name)?;
+        let old_meta = old_inode.metadata()?;
+        // can't move between mounted filesystems
+        if old_meta.dev != new_dir_inode.metadata()?.dev {
+            return Err(SysError::EXDEV);
+        }
+        let same_dir = same_inode(&old_dir_inode, &new_dir_inode)?;
+        if same_dir && old_file_name == new_file_name {
+            return Ok(0);
+        }
+        // both directories gain or lose an entry
+        check_access(&old_dir_inode, W_OK | X_OK, proc.euid, proc.egid)?;
+        check_access(&new_dir_inode, W_OK | X_OK, proc.euid, proc.egid)?;
+        // a directory can't be moved into its own subtree
+        if !same_dir && old_meta.type_ == FileType::Dir {
+            let mut inode = new_dir_inode.clone();
+            loop {
+                if same_inode(&inode, &old_inode)? {
+                    return Err(SysError::EINVAL);
+                }
+                let parent = inode.find("..")?;
+                if same_inode(&parent, &inode)? {
+                    break;
+                }
+                inode = parent;
+            }
+        }
+        // an existing destination is moved aside first,
+        // so it can be put back if the move fails
+        let mut replaced = None;
+        match new_dir_inode.find(new_file_name) {
+            Ok(new_inode) => {
+                if same_inode(&new_inode, &old_inode)? {
+                    return Ok(0);
+                }
+                let new_type = new_inode.metadata()?.type_;
+                match (old_meta.type_, new_type) {
+                    (FileType::Dir, FileType::Dir) => {
+                        if !dir_is_empty(&new_inode) {
+                            return Err(SysError::ENOTEMPTY);
+                        }
+                    }
+                    (FileType::Dir, _) => return Err(SysError::ENOTDIR),
+                    (_, FileType::Dir) => return Err(SysError::EISDIR),
+                    _ => {}
+                }
+                let aside = unused_name(&new_dir_inode);
+                new_dir_inode.move_(new_file_name, &new_dir_inode, &aside)?;
+                replaced = Some(aside);
+            }
+            Err(FsError::EntryNotFound) => {}
+            Err(e) => return Err(e.into()),
+        }
+        if let Err(e) = old_dir_inode.move_(old_file_name, &new_dir_inode, new_file_name) {
+            if let Some(aside) = replaced {
+                new_dir_inode.move_(&aside, &new_dir_inode, new_file_name)?;
+            }
+            return Err(e.into());
+        }
+        if let Some(aside) = replaced {
+            new_dir_inode.unlink(&aside)?;
+        }
+        TimeSpec::update_modified(&old_dir_inode);
+        if !same_dir {
+            TimeSpec::update_modified(&new_dir_inode);
+        }
+        Ok(0)
+    }
+
+    pub fn sys_mknod(&mut self, path: *const u8, mode: usize, dev: usize) -> SysResult {
+        self.sys_mknodat(AT_FDCWD, path, mode, dev)
+    }
+
+    pub fn sys_mknodat(
+        &mut self,
+        dirfd: usize,
+        path: *const u8,
+        mode: usize,
+        dev: usize,
+    ) -> SysResult {
+        let proc = self.process();
+        let path = check_and_clone_cstr(path)?;
+        info!(
+            "mknodat: dirfd: {}, path: {:?}, mode: {:#o}, dev: {:#x}",
+            dirfd as isize, path, mode, dev
+        );
+        let type_ = StatMode::from_bits_truncate(mode as u32) & StatMode::TYPE_MASK;
+        let type_ = if type_ == StatMode::NULL || type_ == StatMode::FILE {
+            FileType::File
+        } else if type_ == StatMode::FIFO {
+            FileType::NamedPipe
+        } else if type_ == StatMode::CHAR || type_ == StatMode::BLOCK {
+            // only root may make device nodes
+            if proc.euid != 0 {
+                return Err(SysError::EPERM);
+            }
+            if type_ == StatMode::CHAR {
+                FileType::CharDevice
+            } else {
+                FileType::BlockDevice
+            }
+        } else {
+            // sockets can't be bound through mknod
+            return Err(SysError::EPERM);
+        };
+
+        let (dir_inode, file_name) = proc.lookup_parent_at(dirfd, &path)?;
+        if dir_inode.find(file_name).is_ok() {
+            return Err(SysError::EEXIST);
+        }
+        check_access(&dir_inode, W_OK | X_OK, proc.euid, proc.egid)?;
+        let mode = mode & !proc.umask & 0o7777;
+        // the filesystem keeps the device number of a device node
+        let inode = dir_inode.create2(file_name, type_, mode as u32, dev)?;
+        set_creator(&inode, &proc);
+        TimeSpec::update(&inode);
+        TimeSpec::update_modified(&dir_inode);
         Ok(0)
     }
 
@@ -995,30 +1358,21 @@ impl Syscall<'_> {
             dirfd as isize, path, mode
         );
 
-        let (dir_path, file_name) = split_path(&path);
-        let dir_inode = proc.lookup_inode_at(dirfd, dir_path, true)?;
+        let (dir_inode, file_name) = proc.lookup_parent_at(dirfd, &path)?;
         if dir_inode.find(file_name).is_ok() {
             return Err(SysError::EEXIST);
         }
+        check_access(&dir_inode, W_OK | X_OK, proc.euid, proc.egid)?;
+        let mode = mode & !proc.umask & 0o7777;
         let inode = dir_inode.create(file_name, FileType::Dir, mode as u32)?;
+        set_creator(&inode, &proc);
         TimeSpec::update(&inode);
-        TimeSpec::update(&dir_inode);
+        TimeSpec::update_modified(&dir_inode);
         Ok(0)
     }
 
     pub fn sys_rmdir(&mut self, path: *const u8) -> SysResult {
-        let proc = self.process();
-        let path = check_and_clone_cstr(path)?;
-        info!("rmdir: path: {:?}", path);
-
-        let (dir_path, file_name) = split_path(&path);
-        let dir_inode = proc.lookup_inode(dir_path)?;
-        let file_inode = dir_inode.find(file_name)?;
-        if file_inode.metadata()?.type_ != FileType::Dir {
-            return Err(SysError::ENOTDIR);
-        }
-        dir_inode.unlink(file_name)?;
-        Ok(0)
+        self.sys_unlinkat(AT_FDCWD, path, AtFlags::REMOVEDIR.bits())
     }
 
     pub fn sys_link(&mut self, oldpath: *const u8, newpath: *const u8) -> SysResult {
@@ -1036,16 +1390,31 @@ impl Syscall<'_> {
         let proc = self.process();
         let oldpath = check_and_clone_cstr(oldpath)?;
         let newpath = check_and_clone_cstr(newpath)?;
+        if flags & !(AtFlags::SYMLINK_FOLLOW | AtFlags::EMPTY_PATH).bits() != 0 {
+            return Err(SysError::EINVAL);
+        }
         let flags = AtFlags::from_bits_truncate(flags);
         info!(
             "linkat: olddirfd: {}, oldpath: {:?}, newdirfd: {}, newpath: {:?}, flags: {:?}",
             olddirfd as isize, oldpath, newdirfd as isize, newpath, flags
         );
 
-        let (new_dir_path, new_file_name) = split_path(&newpath);
-        let inode = proc.lookup_inode_at(olddirfd, &oldpath, true)?;
-        let new_dir_inode = proc.lookup_inode_at(newdirfd, new_dir_path, true)?;
+        // like linux, a symlink itself is linked unless asked otherwise
+        let follow = flags.contains(AtFlags::SYMLINK_FOLLOW);
+        let inode = proc.lookup_inode_at(olddirfd, &oldpath, follow)?;
+        let metadata = inode.metadata()?;
+        if metadata.type_ == FileType::Dir {
+            return Err(SysError::EPERM);
+        }
+        let (new_dir_inode, new_file_name) = proc.lookup_parent_at(newdirfd, &newpath)?;
+        if new_dir_inode.metadata()?.dev != metadata.dev {
+            return Err(SysError::EXDEV);
+        }
+        check_access(&new_dir_inode, W_OK | X_OK, proc.euid, proc.egid)?;
+        // the filesystem may still refuse with NotSameFs => EXDEV
         new_dir_inode.link(new_file_name, &inode)?;
+        TimeSpec::update_changed(&inode);
+        TimeSpec::update_modified(&new_dir_inode);
         Ok(0)
     }
 
@@ -1070,18 +1439,22 @@ impl Syscall<'_> {
             "symlinkat: target: {} , newdirfd: {}, linkpath: {}",
             target, newdirfd as isize, linkpath,
         );
-        let (dir_path, filename) = split_path(&linkpath);
-        let dir_inode = proc.lookup_inode_at(newdirfd, dir_path, true)?;
+        if target.is_empty() {
+            return Err(SysError::ENOENT);
+        }
+        let (dir_inode, filename) = proc.lookup_parent_at(newdirfd, &linkpath)?;
 
         // If linkpath exists, it will not be overwritten.
         match dir_inode.find(filename) {
             Ok(_) => Err(SysError::EEXIST),
             Err(e) => match e {
                 FsError::EntryNotFound => {
+                    check_access(&dir_inode, W_OK | X_OK, proc.euid, proc.egid)?;
                     let symlink = dir_inode.create(filename, FileType::SymLink, 0o777)?;
                     symlink.write_at(0, target.as_bytes())?;
+                    set_creator(&symlink, &proc);
                     TimeSpec::update(&symlink);
-                    TimeSpec::update(&dir_inode);
+                    TimeSpec::update_modified(&dir_inode);
                     Ok(0)
                 }
                 _ => Err(e.into()),
@@ -1098,13 +1471,39 @@ impl Syscall<'_> {
             dirfd as isize, path, flags
         );
 
-        let (dir_path, file_name) = split_path(&path);
-        let dir_inode = proc.lookup_inode_at(dirfd, dir_path, true)?;
+        let (dir_inode, file_name) = proc.lookup_parent_at(dirfd, &path)?;
+        if flags.contains(AtFlags::REMOVEDIR) {
+            match file_name {
+                // the root directory
+                "" => return Err(SysError::EBUSY),
+                "." => return Err(SysError::EINVAL),
+                ".." => return Err(SysError::ENOTEMPTY),
+                _ => {}
+            }
+        }
         let file_inode = dir_inode.find(file_name)?;
-        if file_inode.metadata()?.type_ == FileType::Dir {
-            return Err(SysError::EISDIR);
+        let is_dir = file_inode.metadata()?.type_ == FileType::Dir;
+        if flags.contains(AtFlags::REMOVEDIR) {
+            if !is_dir {
+                return Err(SysError::ENOTDIR);
+            }
+            if !dir_is_empty(&file_inode) {
+                return Err(SysError::ENOTEMPTY);
+            }
+        } else {
+            if is_dir {
+                return Err(SysError::EISDIR);
+            }
+            // trailing slash is only valid for directories
+            if path.ends_with('/') {
+                return Err(SysError::ENOTDIR);
+            }
         }
+        check_access(&dir_inode, W_OK | X_OK, proc.euid, proc.egid)?;
+        // the fs drops nlink here, data is freed once
+        // the last link and the last open handle are gone
         dir_inode.unlink(file_name)?;
+        TimeSpec::update_modified(&dir_inode);
         Ok(0)
     }
 
@@ -1130,20 +1529,27 @@ impl Syscall<'_> {
             String::from("pipe_r:[]"),
             true,
             (flags & O_CLOEXEC) != 0,
-        )));
+        )))?;
 
-        let write_fd = proc.add_file(FileLike::File(FileHandle::new(
+        let write_fd = match proc.add_file(FileLike::File(FileHandle::new(
             Arc::new(write),
             OpenOptions {
                 read: false,
                 write: true,
                 append: false,
-                nonblock: false,
+                nonblock: (flags & O_NONBLOCK) != 0,
             },
             String::from("pipe_w:[]"),
             true,
             (flags & O_CLOEXEC) != 0,
-        )));
+        ))) {
+            Ok(fd) => fd,
+            Err(err) => {
+                // no room for both ends
+                proc.files.remove(&read_fd);
+                return Err(err);
+            }
+        };
 
         fds[0] = read_fd as u32;
         fds[1] = write_fd as u32;
@@ -1174,6 +1580,11 @@ impl Syscall<'_> {
             let times = unsafe { self.vm().check_read_array(times, 2)? };
             [times[0], times[1]]
         };
+        for time in times.iter() {
+            if time.nsec >= 1_000_000_000 && time.nsec != UTIME_NOW && time.nsec != UTIME_OMIT {
+                return Err(EINVAL);
+            }
+        }
         let mut inode = if pathname.is_null() {
             let fd = dirfd;
             info!("futimens: fd: {}, times: {:?}", fd, times);
@@ -1210,12 +1621,185 @@ impl Syscall<'_> {
                 nsec: times[1].nsec as i32,
             };
         }
+        // any explicit change is a status change
+        if times[0].nsec != UTIME_OMIT || times[1].nsec != UTIME_OMIT {
+            metadata.ctime = TimeSpec::get_epoch().into();
+        }
         inode.set_metadata(&metadata)?;
         Ok(0)
     }
 
+    pub fn sys_umask(&mut self, mask: usize) -> SysResult {
+        info!("umask: mask: {:#o}", mask);
+        let mut proc = self.process();
+        let old = proc.umask;
+        proc.umask = mask & 0o777;
+        Ok(old)
+    }
+
+    pub fn sys_chmod(&mut self, path: *const u8, mode: usize) -> SysResult {
+        self.sys_fchmodat(AT_FDCWD, path, mode)
+    }
+
+    pub fn sys_fchmod(&mut self, fd: usize, mode: usize) -> SysResult {
+        info!("fchmod: fd: {}, mode: {:#o}", fd, mode);
+        let mut proc = self.process();
+        let inode = proc.get_file(fd)?.inode();
+        set_mode(&inode, mode, proc.euid)?;
+        Ok(0)
+    }
+
+    pub fn sys_fchmodat(&mut self, dirfd: usize, path: *const u8, mode: usize) -> SysResult {
+        let proc = self.process();
+        let path = check_and_clone_cstr(path)?;
+        info!(
+            "fchmodat: dirfd: {}, path: {:?}, mode: {:#o}",
+            dirfd as isize, path, mode
+        );
+        let inode = proc.lookup_inode_at(dirfd, &path, true)?;
+        set_mode(&inode, mode, proc.euid)?;
+        Ok(0)
+    }
+
+    pub fn sys_chown(&mut self, path: *const u8, uid: usize, gid: usize) -> SysResult {
+        self.sys_fchownat(AT_FDCWD, path, uid, gid, 0)
+    }
+
+    pub fn sys_lchown(&mut self, path: *const u8, uid: usize, gid: usize) -> SysResult {
+        let flags = AtFlags::SYMLINK_NOFOLLOW.bits();
+        self.sys_fchownat(AT_FDCWD, path, uid, gid, flags)
+    }
+
+    pub fn sys_fchown(&mut self, fd: usize, uid: usize, gid: usize) -> SysResult {
+        info!("fchown: fd: {}, uid: {}, gid: {}", fd, uid as i32, gid as i32);
+        let mut proc = self.process();
+        let inode = proc.get_file(fd)?.inode();
+        set_owner(&inode, uid, gid, proc.euid, proc.egid)?;
+        Ok(0)
+    }
+
+    pub fn sys_fchownat(
+        &mut self,
+        dirfd: usize,
+        path: *const u8,
+        uid: usize,
+        gid: usize,
+        flags: usize,
+    ) -> SysResult {
+        let proc = self.process();
+        let path = check_and_clone_cstr(path)?;
+        let flags = AtFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
+        info!(
+            "fchownat: dirfd: {}, path: {:?}, uid: {}, gid: {}, flags: {:?}",
+            dirfd as isize, path, uid as i32, gid as i32, flags
+        );
+        if path.is_empty() && !flags.contains(AtFlags::EMPTY_PATH) {
+            return Err(SysError::ENOENT);
+        }
+        let inode =
+            proc.lookup_inode_at(dirfd, &path, !flags.contains(AtFlags::SYMLINK_NOFOLLOW))?;
+        set_owner(&inode, uid, gid, proc.euid, proc.egid)?;
+        Ok(0)
+    }
+
+    pub fn sys_mount(
+        &mut self,
+        source: *const u8,
+        target: *const u8,
+        fstype: *const u8,
+        flags: usize,
+        _data: *const u8,
+    ) -> SysResult {
+        let proc = self.process();
+        let source = check_and_clone_cstr(source)?;
+        let target = check_and_clone_cstr(target)?;
+        let fstype = check_and_clone_cstr(fstype)?;
+        info!(
+            "mount: source: {:?}, target: {:?}, fstype: {:?}, flags: {:#x}",
+            source, target, fstype, flags
+        );
+
+        if proc.euid != 0 {
+            return Err(SysError::EPERM);
+        }
+        // only memory backed filesystems can be mounted for now
+        let fs: Arc<dyn FileSystem> = match fstype.as_str() {
+            "tmpfs" | "ramfs" => RamFS::new(),
+            _ => return Err(SysError::ENODEV),
+        };
+        let target = absolute_path(&proc.cwd, &target);
+        if MOUNTS.lock().iter().any(|entry| entry.target == target) {
+            return Err(SysError::EBUSY);
+        }
+        // the mount table is not locked while walking the path
+        let mnode = proc.lookup_mount_point(&target)?;
+        let metadata = mnode.metadata()?;
+        if metadata.type_ != FileType::Dir {
+            return Err(SysError::ENOTDIR);
+        }
+        let mut mounts = MOUNTS.lock();
+        if mounts.iter().any(|entry| entry.target == target) {
+            return Err(SysError::EBUSY);
+        }
+        // a mount point unmounted before is attached to again,
+        // as the mount layer only looks one filesystem deep
+        let mut detached = DETACHED.lock();
+        let (mount_fs, detachable) = match detached.iter().position(|(_, d)| d.covers(&metadata)) {
+            Some(index) => {
+                let (mount_fs, detachable) = detached.remove(index);
+                detachable.attach(fs);
+                (mount_fs, detachable)
+            }
+            None => {
+                let detachable = DetachableFS::new(fs, mnode.clone());
+                (mnode.mount(detachable.clone())?, detachable)
+            }
+        };
+        mounts.push(MountEntry {
+            source,
+            target,
+            fstype,
+            fs: mount_fs,
+            detachable,
+        });
+        Ok(0)
+    }
+
+    pub fn sys_umount2(&mut self, target: *const u8, flags: usize) -> SysResult {
+        let proc = self.process();
+        let target = check_and_clone_cstr(target)?;
+        info!("umount2: target: {:?}, flags: {:#x}", target, flags);
+        if flags & !UMOUNT_FLAGS != 0 {
+            return Err(SysError::EINVAL);
+        }
+        if proc.euid != 0 {
+            return Err(SysError::EPERM);
+        }
+        let target = absolute_path(&proc.cwd, &target);
+        let mut mounts = MOUNTS.lock();
+        let index = mounts
+            .iter()
+            .position(|entry| entry.target == target)
+            .ok_or(SysError::EINVAL)?;
+        let entry = mounts.remove(index);
+        entry.detachable.detach();
+        DETACHED.lock().push((entry.fs, entry.detachable));
+        Ok(0)
+    }
+
     pub fn sys_sync(&mut self) -> SysResult {
-        ROOT_INODE.fs().sync()?;
+        use crate::drivers::{BLOCK_READS, BLOCK_WRITES};
+        use core::sync::atomic::Ordering;
+        info!("sync");
+        crate::fs::sync()?;
+        let (hits, misses) = block_cache_stats();
+        info!(
+            "sync: {} blocks read from and {} written to the device so far, block cache hits: {}, misses: {}",
+            BLOCK_READS.load(Ordering::Relaxed),
+            BLOCK_WRITES.load(Ordering::Relaxed),
+            hits,
+            misses
+        );
         Ok(0)
     }
 
@@ -1243,12 +1827,19 @@ impl Syscall<'_> {
             "copy_file_range:BEG in: {}, out: {}, in_offset: {:?}, out_offset: {:?}, count: {} flags {}",
             in_fd, out_fd, in_offset, out_offset, count, flags
         );
-        let proc = self.process();
-        // We know it's save, pacify the borrow checker
-        let proc_cell = UnsafeCell::new(proc);
-        let in_file = unsafe { (*proc_cell.get()).get_file(in_fd)? };
-        let out_file = unsafe { (*proc_cell.get()).get_file(out_fd)? };
-        drop(proc_cell);
+        // the clones share the open file descriptions, so offsets still move.
+        // this also works when in_fd == out_fd, and the process lock is not held
+        // while waiting for data
+        let (mut in_file, mut out_file) = {
+            let mut proc = self.process();
+            (proc.get_file(in_fd)?.clone(), proc.get_file(out_fd)?.clone())
+        };
+        use crate::fs::fcntl::{O_ACCMODE, O_RDONLY, O_WRONLY};
+        if in_file.get_options() & O_ACCMODE == O_WRONLY
+            || out_file.get_options() & O_ACCMODE == O_RDONLY
+        {
+            return Err(SysError::EBADF);
+        }
         let mut buffer = [0u8; 1024];
 
         // for in_offset and out_offset
@@ -1273,17 +1864,22 @@ impl Syscall<'_> {
         let mut total_written = 0;
         while bytes_read < count {
             let len = min(buffer.len(), count - bytes_read);
-            let read_len = in_file.read_at(read_offset, &mut buffer[..len]).await?;
-            if read_len == 0 {
-                break;
-            }
+            let read_len = match in_file.read_at(read_offset, &mut buffer[..len]).await {
+                Ok(0) => break,
+                Ok(read_len) => read_len,
+                // report what was copied so far
+                Err(_) if total_written > 0 => break,
+                Err(err) => return Err(err),
+            };
             bytes_read += read_len;
             read_offset += read_len;
 
             let mut bytes_written = 0;
             let mut rlen = read_len;
             while bytes_written < read_len {
-                let write_len = out_file.write(&buffer[bytes_written..(bytes_written + rlen)])?;
+                let write_len = out_file
+                    .write(&buffer[bytes_written..(bytes_written + rlen)])
+                    .await?;
                 if write_len == 0 {
                     info!(
                         "copy_file_range:END_ERR in: {}, out: {}, in_offset: {:?}, out_offset: {:?}, count: {} = bytes_read {}, bytes_written {}, write_len {}",
@@ -1316,32 +1912,33 @@ impl Syscall<'_> {
 
     pub fn sys_fcntl(&mut self, fd: usize, cmd: usize, arg: usize) -> SysResult {
         info!("fcntl: fd: {}, cmd: {:#x}, arg: {}", fd, cmd, arg);
+        use crate::fs::fcntl::*;
         let mut proc = self.process();
-        let file_like = proc.get_file_like(fd)?;
-        match file_like {
-            FileLike::File(file) => {
-                use crate::fs::fcntl::*;
-                match cmd {
-                    F_SETFD => {
-                        file.fd_cloexec = (arg & 1) != 0;
-                        Ok(0)
-                    }
-                    F_GETFD => Ok(file.fd_cloexec as usize),
-                    F_SETFL => {
-                        file.set_options(arg);
-                        Ok(0)
-                    }
-                    F_GETFL => self.unimplemented("F_GETFL", Ok(0)),
-                    F_DUPFD_CLOEXEC => {
-                        info!("fcntl: dupfd_cloexec: arg: {:#x}", arg);
-                        // let file_like = proc.get_file_like(fd1)?.clone();
-                        let new_fd = proc.get_free_fd_from(arg);
-                        core::mem::drop(proc);
-                        self.dup_impl(fd, new_fd, 1)
-                    }
-                    _ => Ok(0),
-                }
+        // any kind of fd can be duplicated
+        if cmd == F_DUPFD || cmd == F_DUPFD_CLOEXEC {
+            info!("fcntl: dupfd: arg: {:#x}", arg);
+            if arg >= proc.file_limit {
+                return Err(SysError::EINVAL);
             }
+            let new_fd = proc.get_free_fd_from(arg)?;
+            core::mem::drop(proc);
+            return self.dup_impl(fd, new_fd, cmd == F_DUPFD_CLOEXEC);
+        }
+        match proc.get_file_like(fd)? {
+            FileLike::File(file) => match cmd {
+                F_SETFD => {
+                    file.fd_cloexec = (arg & FD_CLOEXEC) != 0;
+                    Ok(0)
+                }
+                F_GETFD => Ok(file.fd_cloexec as usize),
+                F_SETFL => {
+                    // the access mode can't be changed
+                    file.set_options(arg);
+                    Ok(0)
+                }
+                F_GETFL => Ok(file.get_options()),
+                _ => Err(SysError::EINVAL),
+            },
             FileLike::Socket(_) => {
                 Ok(0)
                 //TODO
@@ -1361,6 +1958,13 @@ impl Process {
             _ => Err(SysError::EBADF),
         }
     }
+    /// Get a file that has an offset, pipes and sockets give ESPIPE
+    pub fn get_seekable_file(&mut self, fd: usize) -> Result<&mut FileHandle, SysError> {
+        match self.get_file_like(fd)? {
+            FileLike::File(file) if !file.pipe => Ok(file),
+            _ => Err(ESPIPE),
+        }
+    }
     pub fn get_file_const(&self, fd: usize) -> Result<&FileHandle, SysError> {
         match self.files.get(&fd).ok_or(SysError::EBADF)? {
             FileLike::File(file) => Ok(file),
@@ -1388,40 +1992,123 @@ impl Process {
             "lookup_inode_at: dirfd: {:?}, cwd: {:?}, path: {:?}, follow: {:?}",
             dirfd as isize, self.cwd, path, follow
         );
-        // hard code special path
-        match path {
-            "/proc/self/exe" => {
-                return Ok(Arc::new(Pseudo::new(&self.exec_path, FileType::SymLink)));
-            }
-            _ => {}
-        }
-        let (fd_dir_path, fd_name) = split_path(&path);
-        match fd_dir_path {
-            "/proc/self/fd" => {
-                let fd: usize = fd_name.parse().map_err(|_| SysError::EINVAL)?;
-                let fd_path = &self.get_file_const(fd)?.path;
-                return Ok(Arc::new(Pseudo::new(fd_path, FileType::SymLink)));
-            }
-            _ => {}
-        }
-
-        let follow_max_depth = if follow { FOLLOW_MAX_DEPTH } else { 0 };
-        if dirfd == AT_FDCWD {
-            Ok(ROOT_INODE
-                .lookup(&self.cwd)?
-                .lookup_follow(path, follow_max_depth)?)
+        let path = &normalize_path(path);
+        check_name_len(path)?;
+        if path.starts_with('/') {
+            // dirfd is ignored for absolute paths
+            self.walk_path(ROOT_INODE.clone(), path, follow)
+        } else if dirfd == AT_FDCWD {
+            self.walk_path(ROOT_INODE.lookup(&self.cwd)?, path, follow)
         } else {
             let file = match self.files.get(&dirfd).ok_or(SysError::EBADF)? {
                 FileLike::File(file) => file,
                 _ => return Err(SysError::EBADF),
             };
-            Ok(file.lookup_follow(path, follow_max_depth)?)
+            // an empty path means dirfd itself, whatever it is
+            if !path.is_empty() && file.metadata()?.type_ != FileType::Dir {
+                return Err(SysError::ENOTDIR);
+            }
+            self.walk_path(file.inode(), path, follow)
         }
     }
 
+    /// Resolve `path` from `dir` one component at a time,
+    /// each directory passed through must be searchable by the effective ids
+    fn walk_path(
+        &self,
+        dir: Arc<dyn INode>,
+        path: &str,
+        follow: bool,
+    ) -> Result<Arc<dyn INode>, SysError> {
+        let mut follow_times = 0;
+        self.walk_path_follow(dir, path, follow, &mut follow_times)
+    }
+
+    /// `walk_path` with `follow_times` symlinks followed so far,
+    /// the links are followed here so that /proc links to ourselves resolve
+    fn walk_path_follow(
+        &self,
+        mut dir: Arc<dyn INode>,
+        path: &str,
+        follow: bool,
+        follow_times: &mut usize,
+    ) -> Result<Arc<dyn INode>, SysError> {
+        let mut names = path.split('/').filter(|name| !name.is_empty()).peekable();
+        while let Some(name) = names.next() {
+            if dir.metadata()?.type_ != FileType::Dir {
+                return Err(SysError::ENOTDIR);
+            }
+            check_access(&dir, X_OK, self.euid, self.egid)?;
+            let inode = dir.find(name)?;
+            // symlinks before the last component are always followed
+            if (follow || names.peek().is_some()) && inode.metadata()?.type_ == FileType::SymLink {
+                *follow_times += 1;
+                if *follow_times > FOLLOW_MAX_DEPTH {
+                    return Err(SysError::ELOOP);
+                }
+                let target = self.read_link(&inode)?;
+                let base = if target.starts_with('/') {
+                    ROOT_INODE.clone()
+                } else {
+                    dir
+                };
+                dir = self.walk_path_follow(base, &target, true, follow_times)?;
+            } else {
+                dir = inode;
+            }
+        }
+        // only a directory may be followed by a slash
+        if path.ends_with('/') && dir.metadata()?.type_ != FileType::Dir {
+            return Err(SysError::ENOTDIR);
+        }
+        Ok(dir)
+    }
+
+    /// Read the target of the symlink `inode`.
+    ///
+    /// Links in /proc to ourselves are read from `self`, as they can't lock
+    /// the process we are looking up for.
+    pub fn read_link(&self, inode: &Arc<dyn INode>) -> Result<String, SysError> {
+        if let Some(target) = own_link_target(&inode.metadata()?, self) {
+            return Ok(target);
+        }
+        let mut buf = vec![0u8; PATH_MAX];
+        let len = inode.read_at(0, &mut buf)?;
+        buf.truncate(len);
+        String::from_utf8(buf).map_err(|_| SysError::EINVAL)
+    }
+
     pub fn lookup_inode(&self, path: &str) -> Result<Arc<dyn INode>, SysError> {
         self.lookup_inode_at(AT_FDCWD, path, true)
     }
+
+    /// Lookup the parent directory of `path` relative to `dirfd`,
+    /// return it together with the last component of `path`.
+    pub fn lookup_parent_at<'a>(
+        &self,
+        dirfd: usize,
+        path: &'a str,
+    ) -> Result<(Arc<dyn INode>, &'a str), SysError> {
+        let (dir_path, file_name) = split_path(path);
+        check_name_len(file_name)?;
+        let dir_inode = self.lookup_inode_at(dirfd, dir_path, true)?;
+        Ok((dir_inode, file_name))
+    }
+
+    /// Lookup the directory seen at `path` to mount on,
+    /// which is the root of the filesystem mounted there last, if any
+    fn lookup_mount_point(&self, path: &str) -> Result<Arc<MNode>, SysError> {
+        let (dir_inode, name) = self.lookup_parent_at(AT_FDCWD, path)?;
+        if name.is_empty() {
+            // the root can't be mounted over
+            return Err(SysError::EBUSY);
+        }
+        let dir = dir_inode
+            .as_any_ref()
+            .downcast_ref::<MNode>()
+            .ok_or(SysError::EINVAL)?;
+        Ok(dir.find(true, name)?)
+    }
 }
 
 /// Split a `path` str to `(base_path, file_name)`
@@ -1435,6 +2122,181 @@ fn split_path(path: &str) -> (&str, &str) {
     (dir_path, file_name)
 }
 
+/// Collapse repeated slashes and "." components of `path`.
+/// ".." is left to the file system, which resolves it through the parent inode,
+/// so it works across symlinks and can't go above the root
+fn normalize_path(path: &str) -> String {
+    let mut normalized = String::new();
+    if path.starts_with('/') {
+        normalized.push('/');
+    }
+    for component in path.split('/').filter(|&c| !c.is_empty() && c != ".") {
+        if !normalized.is_empty() && !normalized.ends_with('/') {
+            normalized.push('/');
+        }
+        normalized.push_str(component);
+    }
+    if normalized.is_empty() && !path.is_empty() {
+        // "." or "./"
+        normalized.push('.');
+    } else if (path.ends_with('/') || path.ends_with("/.")) && !normalized.ends_with('/') {
+        // only a directory may be followed by a slash
+        normalized.push('/');
+    }
+    normalized
+}
+
+/// Every component of `path` must fit in a directory entry
+fn check_name_len(path: &str) -> Result<(), SysError> {
+    if path.split('/').any(|name| name.len() > NAME_MAX) {
+        return Err(SysError::ENAMETOOLONG);
+    }
+    Ok(())
+}
+
+/// Join `path` to `cwd` and resolve '.' and '..' lexically
+fn absolute_path(cwd: &str, path: &str) -> String {
+    let base = if path.starts_with('/') { "/" } else { cwd };
+    let mut segs: Vec<_> = base.split('/').filter(|&x| x != "").collect();
+    for seg in path.split('/').filter(|&x| x != "") {
+        match seg {
+            "." => {}
+            ".." => {
+                segs.pop();
+            }
+            _ => segs.push(seg),
+        }
+    }
+    let mut absolute = String::new();
+    for seg in segs {
+        absolute.push('/');
+        absolute.push_str(seg);
+    }
+    if absolute.is_empty() {
+        absolute.push('/');
+    }
+    absolute
+}
+
+/// Check whether two inodes refer to the same file
+fn same_inode(a: &Arc<dyn INode>, b: &Arc<dyn INode>) -> Result<bool, SysError> {
+    let (a, b) = (a.metadata()?, b.metadata()?);
+    Ok(a.dev == b.dev && a.inode == b.inode)
+}
+
+/// Check the permission bits of `inode` for `access`, a mask of R_OK, W_OK and X_OK,
+/// as seen by a process with `uid` and `gid`.
+/// Root may read and write anything and search any directory,
+/// but only execute a file with at least one execute bit
+pub fn check_access(
+    inode: &Arc<dyn INode>,
+    access: usize,
+    uid: usize,
+    gid: usize,
+) -> Result<(), SysError> {
+    let metadata = inode.metadata()?;
+    if uid == 0 {
+        if access & X_OK != 0 && metadata.type_ != FileType::Dir && metadata.mode & 0o111 == 0 {
+            return Err(SysError::EACCES);
+        }
+        return Ok(());
+    }
+    // only the first matching class counts, even if a later one grants more
+    let granted = if metadata.uid == uid {
+        metadata.mode >> 6
+    } else if metadata.gid == gid {
+        metadata.mode >> 3
+    } else {
+        metadata.mode
+    } as usize
+        & 0o7;
+    if access & !granted != 0 {
+        return Err(SysError::EACCES);
+    }
+    Ok(())
+}
+
+/// Give a newly created `inode` to the effective ids of `proc`
+fn set_creator(inode: &Arc<dyn INode>, proc: &Process) {
+    if let Ok(mut metadata) = inode.metadata() {
+        metadata.uid = proc.euid;
+        metadata.gid = proc.egid;
+        inode.set_metadata(&metadata).ok();
+    }
+}
+
+/// Change the permission bits of `inode`, keeping its file type.
+/// Only the owner or root may do it
+fn set_mode(inode: &Arc<dyn INode>, mode: usize, euid: usize) -> Result<(), SysError> {
+    let mut metadata = inode.metadata()?;
+    if euid != 0 && metadata.uid != euid {
+        return Err(SysError::EPERM);
+    }
+    metadata.mode = (mode & 0o7777) as u16;
+    metadata.ctime = TimeSpec::get_epoch().into();
+    inode.set_metadata(&metadata)?;
+    Ok(())
+}
+
+/// Change the owner of `inode` on behalf of a process with `euid` and `egid`,
+/// an id of -1 is left unchanged.
+/// Root may set any owner. Anyone else may only give a file they own
+/// to their own group, and never change its owner
+fn set_owner(
+    inode: &Arc<dyn INode>,
+    uid: usize,
+    gid: usize,
+    euid: usize,
+    egid: usize,
+) -> Result<(), SysError> {
+    let mut metadata = inode.metadata()?;
+    let uid = if uid as u32 != u32::MAX {
+        uid as u32 as usize
+    } else {
+        metadata.uid
+    };
+    let gid = if gid as u32 != u32::MAX {
+        gid as u32 as usize
+    } else {
+        metadata.gid
+    };
+    if euid != 0
+        && (uid != metadata.uid
+            || (gid != metadata.gid && (metadata.uid != euid || gid != egid)))
+    {
+        return Err(SysError::EPERM);
+    }
+    if (uid != metadata.uid || gid != metadata.gid) && metadata.type_ != FileType::Dir {
+        // like linux, a new owner doesn't inherit set-user-ID and set-group-ID
+        metadata.mode &= !0o6000;
+    }
+    metadata.uid = uid;
+    metadata.gid = gid;
+    metadata.ctime = TimeSpec::get_epoch().into();
+    inode.set_metadata(&metadata)?;
+    Ok(())
+}
+
+/// A name not used in `dir`, to hold an entry while it is replaced
+fn unused_name(dir: &Arc<dyn INode>) -> String {
+    (0..)
+        .map(|i| format!(".rename.{}", i))
+        .find(|name| dir.find(name).is_err())
+        .unwrap()
+}
+
+/// Check whether a directory only contains "." and ".."
+fn dir_is_empty(dir: &Arc<dyn INode>) -> bool {
+    let mut id = 0;
+    while let Ok(name) = dir.get_entry(id) {
+        if name != "." && name != ".." {
+            return false;
+        }
+        id += 1;
+    }
+    true
+}
+
 impl From<FsError> for SysError {
     fn from(error: FsError) -> Self {
         match error {
@@ -1446,13 +2308,13 @@ impl From<FsError> for SysError {
             FsError::EntryExist => SysError::EEXIST,
             FsError::NotSameFs => SysError::EXDEV,
             FsError::InvalidParam => SysError::EINVAL,
-            FsError::NoDeviceSpace => SysError::ENOMEM,
+            FsError::NoDeviceSpace => SysError::ENOSPC,
             FsError::DirRemoved => SysError::ENOENT,
             FsError::DirNotEmpty => SysError::ENOTEMPTY,
             FsError::WrongFs => SysError::EINVAL,
             FsError::DeviceError => SysError::EIO,
             FsError::IOCTLError => SysError::EINVAL,
-            FsError::NoDevice => SysError::EINVAL,
+            FsError::NoDevice => SysError::ENODEV,
             FsError::Again => SysError::EAGAIN,
             FsError::SymLoop => SysError::ELOOP,
             FsError::Busy => SysError::EBUSY,
@@ -1465,6 +2327,11 @@ bitflags! {
     struct AtFlags: usize {
         const EMPTY_PATH = 0x1000;
         const SYMLINK_NOFOLLOW = 0x100;
+        /// for linkat
+        const SYMLINK_FOLLOW = 0x400;
+        const EACCESS = 0x200;
+        /// for unlinkat, shares its value with EACCESS
+        const REMOVEDIR = 0x200;
     }
 }
 
@@ -1484,6 +2351,10 @@ bitflags! {
         const TRUNCATE = 1 << 9;
         /// append on each write
         const APPEND = 1 << 10;
+        /// return EAGAIN instead of blocking
+        const NONBLOCK = 1 << 11;
+        /// fail if the last component is a symlink
+        const NOFOLLOW = 1 << 17;
         /// close on exec
         const CLOEXEC = 1 << 19;
     }
@@ -1503,7 +2374,7 @@ impl OpenFlags {
             read: self.readable(),
             write: self.writable(),
             append: self.contains(OpenFlags::APPEND),
-            nonblock: false,
+            nonblock: self.contains(OpenFlags::NONBLOCK),
         }
     }
 }
@@ -1538,7 +2409,7 @@ impl<'a> DirentBufWriter<'a> {
             buf,
         }
     }
-    fn try_write(&mut self, inode: u64, type_: u8, name: &str) -> bool {
+    fn try_write(&mut self, inode: u64, offset: u64, type_: u8, name: &str) -> bool {
         let len = ::core::mem::size_of::<LinuxDirent64>() + name.len() + 1;
         let len = (len + 7) / 8 * 8; // align up
         if self.rest_size < len {
@@ -1546,7 +2417,7 @@ impl<'a> DirentBufWriter<'a> {
         }
         let dent = LinuxDirent64 {
             ino: inode,
-            offset: 0,
+            offset,
             reclen: len as u16,
             type_,
             name: [],
@@ -1791,9 +2662,27 @@ impl StatMode {
     }
 }
 
+/// rdev, I/O block size and number of 512B blocks as reported by stat
+fn stat_sizes(info: &Metadata) -> (usize, usize, usize) {
+    use rcore_memory::PAGE_SIZE;
+    // only device nodes have a device number
+    let rdev = match info.type_ {
+        FileType::CharDevice | FileType::BlockDevice => info.rdev,
+        _ => 0,
+    };
+    // memory backed files have neither, count the bytes they hold
+    let (blksize, blocks) = if info.blk_size == 0 {
+        (PAGE_SIZE, (info.size + 511) / 512)
+    } else {
+        (info.blk_size, info.blocks * info.blk_size / 512)
+    };
+    (rdev, blksize, blocks)
+}
+
 impl From<Metadata> for Stat {
     #[cfg(target_arch = "x86_64")]
     fn from(info: Metadata) -> Self {
+        let (rdev, blksize, blocks) = stat_sizes(&info);
         Stat {
             dev: info.dev as u64,
             ino: info.inode as u64,
@@ -1801,10 +2690,10 @@ impl From<Metadata> for Stat {
             nlink: info.nlinks as u64,
             uid: info.uid as u32,
             gid: info.gid as u32,
-            rdev: info.rdev as u64,
+            rdev: rdev as u64,
             size: info.size as u64,
-            blksize: info.blk_size as u64,
-            blocks: info.blocks as u64,
+            blksize: blksize as u64,
+            blocks: blocks as u64,
             atime: TimeSpec {
                 sec: info.atime.sec as usize,
                 nsec: info.atime.nsec as usize,
@@ -1823,6 +2712,7 @@ impl From<Metadata> for Stat {
 
     #[cfg(target_arch = "mips")]
     fn from(info: Metadata) -> Self {
+        let (rdev, blksize, blocks) = stat_sizes(&info);
         Stat {
             dev: info.dev as u64,
             ino: info.inode as u64,
@@ -1830,10 +2720,10 @@ impl From<Metadata> for Stat {
             nlink: info.nlinks as u32,
             uid: info.uid as u32,
             gid: info.gid as u32,
-            rdev: info.rdev as u64,
+            rdev: rdev as u64,
             size: info.size as u64,
-            blksize: info.blk_size as u32,
-            blocks: info.blocks as u64,
+            blksize: blksize as u32,
+            blocks: blocks as u64,
             atime: Timespec {
                 sec: info.atime.sec as i32,
                 nsec: info.atime.nsec,
@@ -1854,6 +2744,7 @@ impl From<Metadata> for Stat {
 
     #[cfg(not(any(target_arch = "x86_64", target_arch = "mips")))]
     fn from(info: Metadata) -> Self {
+        let (rdev, blksize, blocks) = stat_sizes(&info);
         Stat {
             dev: info.dev as u64,
             ino: info.inode as u64,
@@ -1861,10 +2752,10 @@ impl From<Metadata> for Stat {
             nlink: info.nlinks as u32,
             uid: info.uid as u32,
             gid: info.gid as u32,
-            rdev: info.rdev as u64,
+            rdev: rdev as u64,
             size: info.size as u64,
-            blksize: info.blk_size as u32,
-            blocks: info.blocks as u64,
+            blksize: blksize as u32,
+            blocks: blocks as u64,
             atime: info.atime,
             mtime: info.mtime,
             ctime: info.ctime,
@@ -1874,10 +2765,118 @@ impl From<Metadata> for Stat {
     }
 }
 
+#[cfg(not(target_arch = "mips"))]
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct StatFs {
+    /// type of filesystem
+    type_: usize,
+    /// optimal transfer block size
+    bsize: usize,
+    /// total data blocks in filesystem
+    blocks: usize,
+    /// free blocks in filesystem
+    bfree: usize,
+    /// free blocks available to unprivileged user
+    bavail: usize,
+    /// total file nodes in filesystem
+    files: usize,
+    /// free file nodes in filesystem
+    ffree: usize,
+    /// filesystem id
+    fsid: [u32; 2],
+    /// maximum length of filenames
+    namelen: usize,
+    /// fragment size
+    frsize: usize,
+    /// mount flags of filesystem
+    flags: usize,
+    /// padding
+    _spare: [usize; 4],
+}
+
+#[cfg(target_arch = "mips")]
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct StatFs {
+    /// type of filesystem
+    type_: usize,
+    /// optimal transfer block size
+    bsize: usize,
+    /// fragment size
+    frsize: usize,
+    /// total data blocks in filesystem
+    blocks: usize,
+    /// free blocks in filesystem
+    bfree: usize,
+    /// total file nodes in filesystem
+    files: usize,
+    /// free file nodes in filesystem
+    ffree: usize,
+    /// free blocks available to unprivileged user
+    bavail: usize,
+    /// filesystem id
+    fsid: [u32; 2],
+    /// maximum length of filenames
+    namelen: usize,
+    /// mount flags of filesystem
+    flags: usize,
+    /// padding
+    _spare: [usize; 5],
+}
+
+impl StatFs {
+    /// Stats of the filesystem holding `inode`
+    fn of(inode: &Arc<dyn INode>) -> Result<Self, SysError> {
+        use crate::memory::{FRAMES_IN_USE, FRAMES_TOTAL};
+        use core::sync::atomic::Ordering;
+        use rcore_memory::PAGE_SIZE;
+        let dev = inode.metadata()?.dev;
+        let mut info = inode.fs().info();
+        // memory backed filesystems don't know their sizes,
+        // they can grow as long as there are free frames
+        if info.blocks == 0 {
+            let total = FRAMES_TOTAL.load(Ordering::Relaxed);
+            let free = total.saturating_sub(FRAMES_IN_USE.load(Ordering::Relaxed));
+            info.bsize = PAGE_SIZE;
+            info.frsize = PAGE_SIZE;
+            info.blocks = total;
+            info.bfree = free;
+            info.bavail = free;
+        }
+        let bsize = if info.bsize == 0 { PAGE_SIZE } else { info.bsize };
+        Ok(StatFs {
+            bsize,
+            frsize: if info.frsize == 0 { bsize } else { info.frsize },
+            blocks: info.blocks,
+            bfree: info.bfree,
+            bavail: info.bavail,
+            files: info.files,
+            ffree: info.ffree,
+            fsid: [dev as u32, (dev as u64 >> 32) as u32],
+            namelen: if info.namemax == 0 { NAME_MAX } else { info.namemax },
+            ..StatFs::default()
+        })
+    }
+}
+
+pub const X_OK: usize = 1;
+pub const W_OK: usize = 2;
+pub const R_OK: usize = 4;
+
 const SEEK_SET: u8 = 0;
 const SEEK_CUR: u8 = 1;
 const SEEK_END: u8 = 2;
 
+/// Max length of a file name, not counting the NUL
+const NAME_MAX: usize = 255;
+
+/// Max number of iovecs in a single readv/writev
+const IOV_MAX: usize = 1024;
+
+/// MNT_FORCE | MNT_DETACH | MNT_EXPIRE | UMOUNT_NOFOLLOW
+const UMOUNT_FLAGS: usize = 0xf;
+
 #[derive(Debug, Copy, Clone)]
 #[repr(C)]
 pub struct IoVec {
@@ -1898,7 +2897,18 @@ impl IoVecs {
         vm: &MemorySet,
         readv: bool,
     ) -> Result<Self, SysError> {
+        if iov_count > IOV_MAX {
+            return Err(SysError::EINVAL);
+        }
         let iovs = vm.check_read_array(iov_ptr, iov_count)?.to_vec();
+        // the total length must fit in the return value
+        let mut total_len: usize = 0;
+        for iov in iovs.iter() {
+            total_len = total_len
+                .checked_add(iov.len)
+                .filter(|&len| len <= isize::max_value() as usize)
+                .ok_or(SysError::EINVAL)?;
+        }
         let mut slices = vec![];
         slices.reserve(iovs.len());
         // check all bufs in iov
@@ -4,6 +4,7 @@ use rcore_memory::memory_set::MemoryAttr;
 use rcore_memory::PAGE_SIZE;
 
 use super::*;
+use crate::consts::USER_STACK_OFFSET;
 use crate::memory::GlobalFrameAlloc;
 
 impl Syscall<'_> {
@@ -23,6 +24,22 @@ impl Syscall<'_> {
             addr, len, prot, flags, fd as isize, offset
         );
 
+        if len == 0 || offset % PAGE_SIZE != 0 {
+            return Err(SysError::EINVAL);
+        }
+        if flags.contains(MmapFlags::FIXED) && addr % PAGE_SIZE != 0 {
+            return Err(SysError::EINVAL);
+        }
+        // exactly one of SHARED and PRIVATE must be given
+        if flags.contains(MmapFlags::SHARED) == flags.contains(MmapFlags::PRIVATE) {
+            return Err(SysError::EINVAL);
+        }
+        // map whole pages
+        let len = len
+            .checked_add(PAGE_SIZE - 1)
+            .ok_or(SysError::ENOMEM)?
+            & !(PAGE_SIZE - 1);
+
         let mut proc = self.process();
         let mut addr = addr;
         if addr == 0 {
@@ -74,30 +91,75 @@ impl Syscall<'_> {
     }
 
     pub fn sys_mprotect(&mut self, addr: usize, len: usize, prot: usize) -> SysResult {
-        let prot = MmapProt::from_bits_truncate(prot);
         info!(
-            "mprotect: addr={:#x}, size={:#x}, prot={:?}",
+            "mprotect: addr={:#x}, size={:#x}, prot={:#x}",
             addr, len, prot
         );
-        let _attr = prot.to_attr();
-
-        // TODO: properly set the attribute of the area
-        //        now some mut ptr check is fault
-        let vm = self.vm();
-        let memory_area = vm
-            .iter()
-            .find(|area| area.is_overlap_with(addr, addr + len));
-        if memory_area.is_none() {
-            return Err(SysError::ENOMEM);
+        let prot = MmapProt::from_bits(prot).ok_or(SysError::EINVAL)?;
+        if addr % PAGE_SIZE != 0 {
+            return Err(SysError::EINVAL);
+        }
+        let end = addr
+            .checked_add(len)
+            .and_then(|end| end.checked_add(PAGE_SIZE - 1))
+            .ok_or(SysError::EINVAL)?
+            & !(PAGE_SIZE - 1);
+        if end == addr {
+            return Ok(0);
         }
+        // fails only if part of the range is not mapped
+        self.vm()
+            .protect(addr, end, prot.to_protect_attr())
+            .map_err(|_| SysError::ENOMEM)?;
         Ok(0)
     }
 
     pub fn sys_munmap(&mut self, addr: usize, len: usize) -> SysResult {
         info!("munmap addr={:#x}, size={:#x}", addr, len);
-        self.vm().pop_with_split(addr, addr + len);
+        if addr % PAGE_SIZE != 0 || len == 0 {
+            return Err(SysError::EINVAL);
+        }
+        let end = addr.checked_add(len).ok_or(SysError::EINVAL)?;
+        // unmapped pages in the range are simply skipped,
+        // areas covered partially are split
+        self.vm().pop_with_split(addr, end);
         Ok(0)
     }
+
+    pub fn sys_brk(&mut self, addr: usize) -> SysResult {
+        info!("brk: addr={:#x}", addr);
+        let mut proc = self.process();
+        // on failure the current break is returned, as linux does
+        if addr < proc.brk_start {
+            return Ok(proc.brk);
+        }
+        let old_end = (proc.brk + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let new_end = match addr.checked_add(PAGE_SIZE - 1) {
+            Some(end) => end & !(PAGE_SIZE - 1),
+            None => return Ok(proc.brk),
+        };
+        if new_end > old_end {
+            let mut vm = self.vm();
+            // refuse to grow into an existing mapping or past the user stack
+            if new_end > USER_STACK_OFFSET
+                || vm.iter().any(|area| area.is_overlap_with(old_end, new_end))
+            {
+                return Ok(proc.brk);
+            }
+            vm.push(
+                old_end,
+                new_end,
+                MemoryAttr::default().user(),
+                Delay::new(GlobalFrameAlloc),
+                "heap",
+            );
+        } else if new_end < old_end {
+            // frames of the dropped pages are released
+            self.vm().pop_with_split(new_end, old_end);
+        }
+        proc.brk = addr;
+        Ok(addr)
+    }
 }
 
 bitflags! {
@@ -147,8 +209,20 @@ impl MmapProt {
         if self.contains(MmapProt::EXEC) {
             attr = attr.execute();
         }
-        // TODO: see sys_mprotect
-        //        if !self.contains(MmapProt::WRITE) { attr = attr.readonly(); }
+        // TODO: mmap maps everything writable and accessible,
+        //        only mprotect applies the rest for now
+        attr
+    }
+
+    /// Attributes set by mprotect, which also takes away write and all access
+    pub fn to_protect_attr(self) -> MemoryAttr {
+        let mut attr = self.to_attr();
+        if !self.contains(MmapProt::WRITE) {
+            attr = attr.readonly();
+        }
+        if self.is_empty() {
+            attr = attr.inaccessible();
+        }
         attr
     }
 }
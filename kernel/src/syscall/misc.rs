@@ -3,6 +3,7 @@
 use super::*;
 use crate::arch::cpu;
 use crate::consts::{ARCH, USER_STACK_SIZE};
+use crate::random::get_random_bytes;
 use crate::syscall::SysError::ETIMEDOUT;
 use crate::trap::TICK_ACTIVITY;
 use core::mem::size_of;
@@ -124,7 +125,16 @@ impl Syscall<'_> {
         _arg: *const u8,
     ) -> SysResult {
         // we will skip verifying magic
-        if cmd == LINUX_REBOOT_CMD_HALT {
+        if cmd == LINUX_REBOOT_CMD_HALT
+            || cmd == LINUX_REBOOT_CMD_POWER_OFF
+            || cmd == LINUX_REBOOT_CMD_RESTART
+        {
+            // flush the filesystem and the block cache so the image stays consistent
+            if let Err(e) = crate::fs::sync() {
+                warn!("reboot: failed to sync filesystem: {:?}", e);
+            }
+        }
+        if cmd == LINUX_REBOOT_CMD_HALT || cmd == LINUX_REBOOT_CMD_POWER_OFF {
             unsafe {
                 cpu::exit_in_qemu(0);
             }
@@ -159,13 +169,44 @@ impl Syscall<'_> {
                 Ok(0)
             }
             RLIMIT_NOFILE => {
+                // read our own ids before locking another process
+                let (uid, gid, euid) = {
+                    let proc = self.process();
+                    (proc.uid, proc.gid, proc.euid)
+                };
+                let proc = match pid {
+                    0 => self.thread.proc.clone(),
+                    pid => process(pid).ok_or(SysError::ESRCH)?,
+                };
+                let mut proc = proc.lock();
+                // like linux, others' limits need root or all of their ids to be ours
+                let same_ids = [proc.uid, proc.euid, proc.suid].iter().all(|&id| id == uid)
+                    && [proc.gid, proc.egid, proc.sgid].iter().all(|&id| id == gid);
+                if euid != 0 && !same_ids {
+                    return Err(SysError::EPERM);
+                }
                 if !old_limit.is_null() {
                     let old_limit = unsafe { self.vm().check_write_ptr(old_limit)? };
                     *old_limit = RLimit {
-                        cur: 1024,
-                        max: 1024,
+                        cur: proc.file_limit as u64,
+                        max: proc.file_limit_max as u64,
                     };
                 }
+                if !new_limit.is_null() {
+                    let new_limit = unsafe { self.vm().check_read_ptr(new_limit)? };
+                    if new_limit.cur > new_limit.max {
+                        return Err(SysError::EINVAL);
+                    }
+                    // only root may raise the hard limit, up to the global ceiling
+                    if new_limit.max > proc.file_limit_max as u64 && euid != 0
+                        || new_limit.max > MAX_FILE_LIMIT as u64
+                    {
+                        return Err(SysError::EPERM);
+                    }
+                    // fds already open above the new limit stay open
+                    proc.file_limit = new_limit.cur as usize;
+                    proc.file_limit_max = new_limit.max as usize;
+                }
                 Ok(0)
             }
             RLIMIT_RSS | RLIMIT_AS => {
@@ -183,22 +224,29 @@ impl Syscall<'_> {
         }
     }
 
-    pub fn sys_getrandom(&mut self, buf: *mut u8, len: usize, _flag: u32) -> SysResult {
-        //info!("getrandom: buf: {:?}, len: {:?}, falg {:?}", buf, len,flag);
-        let slice = unsafe { self.vm().check_write_array(buf, len)? };
-        let mut i = 0;
-        for elm in slice {
-            unsafe {
-                // to prevent overflow
-                *elm = (i + crate::trap::TICK as u8 as u16) as u8;
-            }
-            i += 1;
-        }
+    pub fn sys_getrlimit(&mut self, resource: usize, rlim: *mut RLimit) -> SysResult {
+        self.sys_prlimit64(0, resource, core::ptr::null(), rlim)
+    }
+
+    pub fn sys_setrlimit(&mut self, resource: usize, rlim: *const RLimit) -> SysResult {
+        self.sys_prlimit64(0, resource, rlim, core::ptr::null_mut())
+    }
 
+    pub fn sys_getrandom(&mut self, buf: *mut u8, len: usize, flags: u32) -> SysResult {
+        info!("getrandom: buf: {:?}, len: {:#x}, flags: {:#x}", buf, len, flags);
+        if flags & !(GRND_NONBLOCK | GRND_RANDOM) != 0 {
+            return Err(SysError::EINVAL);
+        }
+        let slice = unsafe { self.vm().check_write_array(buf, len)? };
+        // the pool never blocks, so the flags change nothing
+        get_random_bytes(slice);
         Ok(len)
     }
 }
 
+const GRND_NONBLOCK: u32 = 1;
+const GRND_RANDOM: u32 = 2;
+
 const LINUX_REBOOT_CMD_RESTART: u32 = 0x01234567;
 const LINUX_REBOOT_CMD_HALT: u32 = 0xCDEF0123;
 const LINUX_REBOOT_CMD_CAD_ON: u32 = 0x89ABCDEF;
@@ -122,8 +122,14 @@ impl Syscall<'_> {
                 self.sys_read(args[0], UserOutPtr::from(args[1]), args[2])
                     .await
             }
-            SYS_WRITE => self.sys_write(args[0], args[1] as *const u8, args[2]),
-            SYS_OPENAT => self.sys_openat(args[0], args[1] as *const u8, args[2], args[3]),
+            SYS_WRITE => {
+                self.sys_write(args[0], args[1] as *const u8, args[2])
+                    .await
+            }
+            SYS_OPENAT => {
+                self.sys_openat(args[0], args[1] as *const u8, args[2], args[3])
+                    .await
+            }
             SYS_CLOSE => self.sys_close(args[0]),
             SYS_FSTAT => self.sys_fstat(args[0], args[1] as *mut Stat),
             SYS_NEWFSTATAT => {
@@ -140,13 +146,16 @@ impl Syscall<'_> {
                 self.sys_readv(args[0], UserInPtr::from(args[1]), args[2])
                     .await
             }
-            SYS_WRITEV => self.sys_writev(args[0], args[1] as *const IoVec, args[2]),
+            SYS_WRITEV => {
+                self.sys_writev(args[0], args[1] as *const IoVec, args[2])
+                    .await
+            }
             SYS_SENDFILE => {
                 self.sys_sendfile(args[0], args[1], UserInOutPtr::from(args[2]), args[3])
                     .await
             }
             SYS_FCNTL => self.sys_fcntl(args[0], args[1], args[2]),
-            SYS_FLOCK => self.sys_flock(args[0], args[1]),
+            SYS_FLOCK => self.sys_flock(args[0], args[1]).await,
             SYS_FSYNC => self.sys_fsync(args[0]),
             SYS_FDATASYNC => self.sys_fdatasync(args[0]),
             SYS_TRUNCATE => self.sys_truncate(args[0] as *const u8, args[1]),
@@ -157,6 +166,7 @@ impl Syscall<'_> {
             SYS_RENAMEAT => {
                 self.sys_renameat(args[0], args[1] as *const u8, args[2], args[3] as *const u8)
             }
+            SYS_MKNODAT => self.sys_mknodat(args[0], args[1] as *const u8, args[2], args[3]),
             SYS_MKDIRAT => self.sys_mkdirat(args[0], args[1] as *const u8, args[2]),
             SYS_LINKAT => self.sys_linkat(
                 args[0],
@@ -172,11 +182,14 @@ impl Syscall<'_> {
             SYS_READLINKAT => {
                 self.sys_readlinkat(args[0], args[1] as *const u8, args[2] as *mut u8, args[3])
             }
-            SYS_FCHMOD => self.unimplemented("fchmod", Ok(0)),
-            SYS_FCHMODAT => self.unimplemented("fchmodat", Ok(0)),
-            SYS_FCHOWN => self.unimplemented("fchown", Ok(0)),
-            SYS_FCHOWNAT => self.unimplemented("fchownat", Ok(0)),
+            SYS_FCHMOD => self.sys_fchmod(args[0], args[1]),
+            SYS_FCHMODAT => self.sys_fchmodat(args[0], args[1] as *const u8, args[2]),
+            SYS_FCHOWN => self.sys_fchown(args[0], args[1], args[2]),
+            SYS_FCHOWNAT => {
+                self.sys_fchownat(args[0], args[1] as *const u8, args[2], args[3], args[4])
+            }
             SYS_FACCESSAT => self.sys_faccessat(args[0], args[1] as *const u8, args[2], args[3]),
+            SYS_DUP => self.sys_dup(args[0]),
             SYS_DUP3 => self.sys_dup3(args[0], args[1], args[2]),
             SYS_PIPE2 => self.sys_pipe2(args[0] as *mut u32, args[1]), // TODO: handle `flags`
             SYS_SET_ROBUST_LIST => self.unimplemented("set_robuts_list", Ok(0)),
@@ -205,7 +218,7 @@ impl Syscall<'_> {
                 args[1] as *mut u32,
                 args[2] as *mut u32,
                 args[3] as *mut u32,
-                args[4] as *const TimeVal,
+                args[4] as *const TimeSpec,
                 args[5] as *const u32,
             ),
             SYS_PPOLL => {
@@ -231,14 +244,20 @@ impl Syscall<'_> {
 
             SYS_SOCKETPAIR => self.unimplemented("socketpair", Err(SysError::EACCES)),
             // file system
-            SYS_STATFS => self.unimplemented("statfs", Err(SysError::EACCES)),
-            SYS_FSTATFS => self.unimplemented("fstatfs", Err(SysError::EACCES)),
+            SYS_STATFS => self.sys_statfs(args[0] as *const u8, args[1] as *mut StatFs),
+            SYS_FSTATFS => self.sys_fstatfs(args[0], args[1] as *mut StatFs),
             SYS_SYNC => self.sys_sync(),
-            SYS_MOUNT => self.unimplemented("mount", Err(SysError::EACCES)),
-            SYS_UMOUNT2 => self.unimplemented("umount2", Err(SysError::EACCES)),
+            SYS_MOUNT => self.sys_mount(
+                args[0] as *const u8,
+                args[1] as *const u8,
+                args[2] as *const u8,
+                args[3],
+                args[4] as *const u8,
+            ),
+            SYS_UMOUNT2 => self.sys_umount2(args[0] as *const u8, args[1]),
 
             // memory
-            SYS_BRK => self.unimplemented("brk", Err(SysError::ENOMEM)),
+            SYS_BRK => self.sys_brk(args[0]),
             SYS_MMAP => self.sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
             SYS_MPROTECT => self.sys_mprotect(args[0], args[1], args[2]),
             SYS_MUNMAP => self.sys_munmap(args[0], args[1]),
@@ -344,7 +363,10 @@ impl Syscall<'_> {
             SYS_TKILL => self.sys_tkill(args[0], args[1]),
 
             // time
-            SYS_NANOSLEEP => self.sys_nanosleep(UserInPtr::from(args[0])).await,
+            SYS_NANOSLEEP => {
+                self.sys_nanosleep(UserInPtr::from(args[0]), UserOutPtr::from(args[1]))
+                    .await
+            }
             SYS_SETITIMER => self.unimplemented("setitimer", Ok(0)),
             SYS_GETTIMEOFDAY => {
                 self.sys_gettimeofday(UserOutPtr::from(args[0]), UserInPtr::from(args[1]))
@@ -379,17 +401,17 @@ impl Syscall<'_> {
             SYS_GETPID => self.sys_getpid(),
             SYS_GETTID => self.sys_gettid(),
             SYS_UNAME => self.sys_uname(args[0] as *mut u8),
-            SYS_UMASK => self.unimplemented("umask", Ok(0o777)),
-            //        SYS_GETRLIMIT => self.sys_getrlimit(),
-            SYS_SETRLIMIT => self.unimplemented("setrlimit", Ok(0)),
+            SYS_UMASK => self.sys_umask(args[0]),
+            SYS_GETRLIMIT => self.sys_getrlimit(args[0], args[1] as *mut RLimit),
+            SYS_SETRLIMIT => self.sys_setrlimit(args[0], args[1] as *const RLimit),
             SYS_GETRUSAGE => self.sys_getrusage(args[0], args[1] as *mut RUsage),
             SYS_SYSINFO => self.sys_sysinfo(args[0] as *mut SysInfo),
             SYS_TIMES => self.sys_times(args[0] as *mut Tms),
-            SYS_GETUID => self.unimplemented("getuid", Ok(0)),
-            SYS_GETGID => self.unimplemented("getgid", Ok(0)),
-            SYS_SETUID => self.unimplemented("setuid", Ok(0)),
-            SYS_GETEUID => self.unimplemented("geteuid", Ok(0)),
-            SYS_GETEGID => self.unimplemented("getegid", Ok(0)),
+            SYS_GETUID => self.sys_getuid(),
+            SYS_GETGID => self.sys_getgid(),
+            SYS_SETUID => self.sys_setuid(args[0]),
+            SYS_GETEUID => self.sys_geteuid(),
+            SYS_GETEGID => self.sys_getegid(),
             SYS_GETPPID => self.sys_getppid(),
             SYS_SETSID => self.unimplemented("setsid", Ok(0)),
             SYS_GETPGID => self.sys_getpgid(args[0]),
@@ -397,9 +419,9 @@ impl Syscall<'_> {
             SYS_GETGROUPS => self.unimplemented("getgroups", Ok(0)),
             SYS_RT_SIGTIMEDWAIT => self.unimplemented("rt_sigtimedwait", Ok(0)),
             SYS_SETGROUPS => self.unimplemented("setgroups", Ok(0)),
-            SYS_SETRESUID => self.unimplemented("setresuid", Ok(0)),
-            SYS_SETRESGID => self.unimplemented("setresgid", Ok(0)),
-            SYS_SETGID => self.unimplemented("setgid", Ok(0)),
+            SYS_SETRESUID => self.sys_setresuid(args[0], args[1], args[2]),
+            SYS_SETRESGID => self.sys_setresgid(args[0], args[1], args[2]),
+            SYS_SETGID => self.sys_setgid(args[0]),
             SYS_SETPRIORITY => self.sys_set_priority(args[0]),
             SYS_PRCTL => self.unimplemented("prctl", Ok(0)),
             SYS_MEMBARRIER => self.unimplemented("membarrier", Ok(0)),
@@ -484,7 +506,7 @@ impl Syscall<'_> {
     #[cfg(target_arch = "mips")]
     async fn mips_syscall(&mut self, id: usize, args: [usize; 6]) -> Option<SysResult> {
         let ret = match id {
-            SYS_OPEN => self.sys_open(args[0] as *const u8, args[1], args[2]),
+            SYS_OPEN => self.sys_open(args[0] as *const u8, args[1], args[2]).await,
             SYS_POLL => {
                 self.sys_poll(UserInOutPtr::from(args[0]), args[1], args[2])
                     .await
@@ -536,7 +558,7 @@ impl Syscall<'_> {
     #[cfg(target_arch = "x86_64")]
     async fn x86_64_syscall(&mut self, id: usize, args: [usize; 6]) -> Option<SysResult> {
         let ret = match id {
-            SYS_OPEN => self.sys_open(args[0] as *const u8, args[1], args[2]),
+            SYS_OPEN => self.sys_open(args[0] as *const u8, args[1], args[2]).await,
             SYS_STAT => self.sys_stat(args[0] as *const u8, args[1] as *mut Stat),
             SYS_LSTAT => self.sys_lstat(args[0] as *const u8, args[1] as *mut Stat),
             SYS_POLL => {
@@ -550,7 +572,7 @@ impl Syscall<'_> {
                 args[1] as *mut u32,
                 args[2] as *mut u32,
                 args[3] as *mut u32,
-                args[4] as *const TimeVal,
+                args[4] as *mut TimeVal,
             ),
             SYS_DUP2 => self.sys_dup2(args[0], args[1]),
             SYS_ALARM => self.unimplemented("alarm", Ok(0)),
@@ -558,13 +580,15 @@ impl Syscall<'_> {
             SYS_VFORK => self.sys_vfork(),
             SYS_RENAME => self.sys_rename(args[0] as *const u8, args[1] as *const u8),
             SYS_MKDIR => self.sys_mkdir(args[0] as *const u8, args[1]),
+            SYS_MKNOD => self.sys_mknod(args[0] as *const u8, args[1], args[2]),
             SYS_RMDIR => self.sys_rmdir(args[0] as *const u8),
             SYS_LINK => self.sys_link(args[0] as *const u8, args[1] as *const u8),
             SYS_UNLINK => self.sys_unlink(args[0] as *const u8),
             SYS_SYMLINK => self.sys_symlink(args[0] as *const u8, args[1] as *const u8),
             SYS_READLINK => self.sys_readlink(args[0] as *const u8, args[1] as *mut u8, args[2]),
-            SYS_CHMOD => self.unimplemented("chmod", Ok(0)),
-            SYS_CHOWN => self.unimplemented("chown", Ok(0)),
+            SYS_CHMOD => self.sys_chmod(args[0] as *const u8, args[1]),
+            SYS_CHOWN => self.sys_chown(args[0] as *const u8, args[1], args[2]),
+            SYS_LCHOWN => self.sys_lchown(args[0] as *const u8, args[1], args[2]),
             SYS_ARCH_PRCTL => self.sys_arch_prctl(args[0] as i32, args[1]),
             SYS_TIME => self.sys_time(args[0] as *mut u64),
             SYS_EPOLL_CREATE => self.sys_epoll_create(args[0]),
@@ -684,6 +708,7 @@ impl fmt::Display for SysError {
                 ENOSYS => "Function not implemented",
                 ENOTEMPTY => "Directory not empty",
                 ELOOP => "Too many symbolic links encountered",
+                EIDRM => "Identifier removed",
                 ENOTSOCK => "Socket operation on non-socket",
                 ENOPROTOOPT => "Protocol not available",
                 EPFNOSUPPORT => "Protocol family not supported",
@@ -691,6 +716,7 @@ impl fmt::Display for SysError {
                 ENOBUFS => "No buffer space available",
                 EISCONN => "Transport endpoint is already connected",
                 ENOTCONN => "Transport endpoint is not connected",
+                ETIMEDOUT => "Connection timed out",
                 ECONNREFUSED => "Connection refused",
                 _ => "Unknown error",
             },
@@ -715,12 +741,30 @@ pub fn spin_and_wait<T>(condvars: &[&Condvar], mut action: impl FnMut() -> Optio
     Condvar::wait_events(&condvars, action)
 }
 
+/// Max length of a path, including the trailing NUL
+pub const PATH_MAX: usize = 4096;
+
+/// Max length of a single argv or envp string, including the trailing NUL
+const MAX_ARG_STRLEN: usize = 0x20000;
+
+/// Copy a path from user space, see `check_and_clone_cstr_bounded`
 pub fn check_and_clone_cstr(user: *const u8) -> Result<String, SysError> {
+    check_and_clone_cstr_bounded(user, PATH_MAX)
+}
+
+/// Copy a NUL-terminated string of at most `max_len` bytes (NUL included)
+/// from user space. Every byte is read with a fault-checked copy, so the string
+/// may end just before an unmapped page. Too long a string is ENAMETOOLONG,
+/// one that is not UTF-8 is EINVAL
+pub fn check_and_clone_cstr_bounded(user: *const u8, max_len: usize) -> Result<String, SysError> {
     if user.is_null() {
         Ok(String::new())
     } else {
         let mut buffer = Vec::new();
         for i in 0.. {
+            if i >= max_len {
+                return Err(SysError::ENAMETOOLONG);
+            }
             let addr = unsafe { user.add(i) };
             let data = copy_from_user(addr).ok_or(SysError::EFAULT)?;
             if data == 0 {
@@ -728,7 +772,7 @@ pub fn check_and_clone_cstr(user: *const u8) -> Result<String, SysError> {
             }
             buffer.push(data);
         }
-        String::from_utf8(buffer).map_err(|_| SysError::EFAULT)
+        String::from_utf8(buffer).map_err(|_| SysError::EINVAL)
     }
 }
 
@@ -743,7 +787,10 @@ pub fn check_and_clone_cstr_array(user: *const *const u8) -> Result<Vec<String>,
             if str_ptr.is_null() {
                 break;
             }
-            let string = check_and_clone_cstr(str_ptr)?;
+            let string = match check_and_clone_cstr_bounded(str_ptr, MAX_ARG_STRLEN) {
+                Err(SysError::ENAMETOOLONG) => return Err(SysError::E2BIG),
+                result => result?,
+            };
             buffer.push(string);
         }
         Ok(buffer)
@@ -39,7 +39,7 @@ impl Syscall<'_> {
             },
             _ => return Err(SysError::EAFNOSUPPORT),
         };
-        let fd = proc.add_file(FileLike::Socket(socket));
+        let fd = proc.add_file(FileLike::Socket(socket))?;
         Ok(fd)
     }
 
@@ -236,7 +236,7 @@ impl Syscall<'_> {
         let socket = proc.get_socket(fd)?;
         let (new_socket, remote_endpoint) = socket.accept()?;
 
-        let new_fd = proc.add_file(FileLike::Socket(new_socket));
+        let new_fd = proc.add_file(FileLike::Socket(new_socket))?;
 
         if !addr.is_null() {
             let sockaddr_in = SockAddr::from(remote_endpoint);
@@ -33,11 +33,14 @@ impl Syscall<'_> {
         self.sys_fork()
     }
 
-    /// Create a new thread in the current process.
-    /// The new thread's stack pointer will be set to `newsp`,
-    /// and thread pointer will be set to `newtls`.
-    /// The child tid will be stored at both `parent_tid` and `child_tid`.
-    /// This is partially implemented for musl only.
+    /// Create a new process or a new thread in the current process.
+    ///
+    /// Without `CLONE_VM`, or with `CLONE_VFORK`, this works like `fork`,
+    /// except that the child starts on `newsp` if it is given.
+    /// Otherwise the new thread shares the address space, files and signal
+    /// handlers of the process, so `CLONE_FILES`, `CLONE_SIGHAND` and
+    /// `CLONE_THREAD` are required too. Its stack pointer is set to `newsp`,
+    /// and its thread pointer to `newtls`.
     pub fn sys_clone(
         &mut self,
         flags: usize,
@@ -51,31 +54,85 @@ impl Syscall<'_> {
             "clone: flags: {:?} == {:#x}, newsp: {:#x}, parent_tid: {:?}, child_tid: {:?}, newtls: {:#x}",
             clone_flags, flags, newsp, parent_tid, child_tid, newtls
         );
-        if flags == 0x4111 || flags == 0x11 {
-            warn!("sys_clone is calling sys_fork instead, ignoring other args");
-            return self.sys_fork();
+        if clone_flags.contains(CloneFlags::VFORK) || !clone_flags.contains(CloneFlags::VM) {
+            // the address space is copied, not shared
+            let mut context = self.context.clone();
+            if newsp != 0 {
+                context.set_sp(newsp);
+            }
+            if clone_flags.contains(CloneFlags::SETTLS) {
+                context.set_tls(newtls);
+            }
+            // check pointers before creating anything
+            let parent_tid_ref = if clone_flags.contains(CloneFlags::PARENT_SETTID) {
+                Some(unsafe { self.vm().check_write_ptr(parent_tid)? })
+            } else {
+                None
+            };
+            if clone_flags.intersects(CloneFlags::CHILD_SETTID | CloneFlags::CHILD_CLEARTID) {
+                unsafe { self.vm().check_write_ptr(child_tid)? };
+            }
+            let new_thread = self.thread.fork(&context);
+            let pid = new_thread.proc.lock().pid.get();
+            info!("clone: {} -> {} (new process)", self.process().pid, pid);
+            if let Some(parent_tid_ref) = parent_tid_ref {
+                *parent_tid_ref = pid as u32;
+            }
+            // these are in the copy of the child, checked above in ours
+            if clone_flags.contains(CloneFlags::CHILD_SETTID) {
+                let tid = (pid as u32).to_ne_bytes();
+                if new_thread
+                    .vm
+                    .lock()
+                    .write_bytes(child_tid as usize, &tid)
+                    .is_err()
+                {
+                    warn!("clone: failed to set the tid of {}", pid);
+                }
+            }
+            if clone_flags.contains(CloneFlags::CHILD_CLEARTID) {
+                new_thread.inner.lock().clear_child_tid = child_tid as usize;
+            }
+            spawn(new_thread);
+            return Ok(pid);
         }
-        if (flags != 0x7d0f00) && (flags != 0x5d0f00) {
-            // 0x5d0f00 is the args from gcc of alpine linux
+        let thread_flags =
+            CloneFlags::VM | CloneFlags::FILES | CloneFlags::SIGHAND | CloneFlags::THREAD;
+        if !clone_flags.contains(thread_flags) {
             warn!(
-                "sys_clone only support sys_fork or musl pthread_create without flags{:x}",
-                flags
+                "sys_clone only supports new processes and threads, not {:?}",
+                clone_flags
             );
             return Err(SysError::ENOSYS);
         }
-        let parent_tid_ref = unsafe { self.vm().check_write_ptr(parent_tid)? };
-        // child_tid buffer should not be set because CLONE_CHILD_SETTID flag is not specified in the current implementation
-        let child_tid_ref = unsafe { self.vm().check_write_ptr(child_tid)? };
-        let mut new_thread = self
+        // check pointers before creating anything
+        let parent_tid_ref = if clone_flags.contains(CloneFlags::PARENT_SETTID) {
+            Some(unsafe { self.vm().check_write_ptr(parent_tid)? })
+        } else {
+            None
+        };
+        let child_tid_ref = if clone_flags.contains(CloneFlags::CHILD_SETTID) {
+            Some(unsafe { self.vm().check_write_ptr(child_tid)? })
+        } else {
+            None
+        };
+        let clear_child_tid = if clone_flags.contains(CloneFlags::CHILD_CLEARTID) {
+            child_tid as usize
+        } else {
+            0
+        };
+        let new_thread = self
             .thread
-            .new_clone(self.context, newsp, newtls, child_tid as usize);
-        if clone_flags.contains(CloneFlags::CHILD_CLEARTID) {
-            new_thread.inner.lock().clear_child_tid = child_tid as usize;
-        }
+            .new_clone(self.context, newsp, newtls, clear_child_tid);
         let tid: usize = new_thread.tid;
         info!("clone: {} -> {}", self.thread.tid, tid);
-        *parent_tid_ref = tid as u32;
-        *child_tid_ref = tid as u32;
+        // the address space is shared, so the child sees these too
+        if let Some(parent_tid_ref) = parent_tid_ref {
+            *parent_tid_ref = tid as u32;
+        }
+        if let Some(child_tid_ref) = child_tid_ref {
+            *child_tid_ref = tid as u32;
+        }
         spawn(new_thread);
         Ok(tid)
     }
@@ -92,54 +149,54 @@ impl Syscall<'_> {
         #[derive(Debug)]
         enum WaitFor {
             AnyChild,
-            AnyChildInGroup,
+            AnyChildInGroup(Pgid),
             Pid(usize),
         }
         let target = match pid {
             -1 => WaitFor::AnyChild,
-            0 => WaitFor::AnyChildInGroup,
+            0 => WaitFor::AnyChildInGroup(self.process().pgid),
             p if p > 0 => WaitFor::Pid(p as usize),
-            _ => unimplemented!(),
+            p => WaitFor::AnyChildInGroup(-p as Pgid),
+        };
+        // only children can be waited for
+        let matches = |pid: Pid, child: &Process| match target {
+            WaitFor::AnyChild => true,
+            WaitFor::AnyChildInGroup(pgid) => child.pgid == pgid,
+            WaitFor::Pid(p) => pid.get() == p,
         };
         loop {
             info!("wait4 loop: pid: {}, code: {:?}", pid, wstatus);
             let mut proc = self.process();
 
             // check child state
-            let find = match target {
-                WaitFor::AnyChild | WaitFor::AnyChildInGroup => {
-                    let mut res = None;
-                    for (pid, child) in &proc.children {
-                        if let Some(c) = child.upgrade() {
-                            let p = c.lock();
-                            if p.exited() {
-                                res = Some((p.pid, p.exit_code));
-                                break;
-                            }
-                        } else {
-                            info!("wait: pid {} is missing", pid);
-                        }
-                    }
-                    res
-                }
-                WaitFor::Pid(pid) => {
-                    let mut res = None;
-                    if let Some(c) = process(pid) {
-                        let p = c.lock();
+            let mut find = None;
+            let mut has_child = false;
+            for (pid, child) in &proc.children {
+                if let Some(c) = child.upgrade() {
+                    let p = c.lock();
+                    if matches(*pid, &p) {
+                        has_child = true;
                         if p.exited() {
-                            res = Some((p.pid, p.exit_code));
+                            // see WIFEXITED and WIFSIGNALED
+                            let status = match p.exit_signal {
+                                0 => (p.exit_code & 0xff) << 8,
+                                signo => signo & 0x7f,
+                            };
+                            find = Some((p.pid, status));
+                            break;
                         }
                     }
-                    res
+                } else {
+                    info!("wait: pid {} is missing", pid);
                 }
-            };
+            }
             // if found, return
-            if let Some((pid, exit_code)) = find {
+            if let Some((pid, status)) = find {
                 info!("wait: found pid {}", pid);
 
                 // write before removing to handle EFAULT
                 if let Some(mut wstatus) = wstatus {
-                    wstatus.write(exit_code as i32)?;
+                    wstatus.write(status as i32)?;
                 }
 
                 // remove from process table
@@ -154,24 +211,7 @@ impl Syscall<'_> {
                 return Ok(pid.get());
             }
             // if not, check pid
-            let invalid = {
-                let children = proc
-                    .children
-                    .iter()
-                    .filter_map(|(pid, weak)| {
-                        if weak.upgrade().is_none() {
-                            None
-                        } else {
-                            Some(pid)
-                        }
-                    })
-                    .collect::<Vec<_>>();
-                match target {
-                    WaitFor::AnyChild | WaitFor::AnyChildInGroup => children.len() == 0,
-                    WaitFor::Pid(pid) => children.iter().find(|p| p.get() == pid).is_none(),
-                }
-            };
-            if invalid {
+            if !has_child {
                 info!("wait: no valid child proc");
                 return Err(SysError::ECHILD);
             }
@@ -219,12 +259,22 @@ impl Syscall<'_> {
 
         // Read program file
         let inode = proc.lookup_inode(&path)?;
+        if inode.metadata()?.type_ != FileType::File {
+            return Err(SysError::EACCES);
+        }
+        check_access(&inode, X_OK, proc.euid, proc.egid)?;
 
         // Make new Thread
-        // Re-create vm
+        // Build the new vm aside, so a bad ELF leaves the old image intact
+        let mut new_vm = MemorySet::new();
+        let (entry_addr, ustack_top, brk) = Thread::new_user_vm(&inode, args, envs, &mut new_vm)
+            .map_err(|err| {
+                warn!("exec: {}", err);
+                SysError::ENOEXEC
+            })?;
+        // the root page table stays, the running thread keeps using its token
         let mut vm = self.vm();
-        let (entry_addr, ustack_top) =
-            Thread::new_user_vm(&inode, args, envs, &mut vm).map_err(|_| SysError::EINVAL)?;
+        vm.replace_with(new_vm);
 
         // Kill other threads
         // TODO: stop and wait until they are finished
@@ -250,7 +300,7 @@ impl Syscall<'_> {
             proc.files.remove(&fd);
         }
 
-        // Activate new page table
+        // Reload the page table to flush stale TLB entries
         unsafe {
             vm.activate();
         }
@@ -259,6 +309,10 @@ impl Syscall<'_> {
         // Modify exec path
         proc.exec_path = path.clone();
 
+        // the old heap is gone with the old vm
+        proc.brk_start = brk;
+        proc.brk = brk;
+
         // reset disposition (man signal(7))
         for d in proc.dispositions.iter_mut() {
             *d = SignalAction::default();
@@ -327,12 +381,105 @@ impl Syscall<'_> {
     /// Get the parent process id
     pub fn sys_getppid(&mut self) -> SysResult {
         info!("getppid");
-        let (pid, parent) = self.process().parent.clone();
+        let proc = self.process();
+        let (pid, parent) = proc.parent.clone();
         if parent.upgrade().is_some() {
             Ok(pid.get())
-        } else {
+        } else if proc.pid.is_init() {
             Ok(0)
+        } else {
+            // orphans belong to init
+            Ok(Pid::INIT)
+        }
+    }
+
+    pub fn sys_getuid(&mut self) -> SysResult {
+        info!("getuid");
+        Ok(self.process().uid)
+    }
+
+    pub fn sys_geteuid(&mut self) -> SysResult {
+        info!("geteuid");
+        Ok(self.process().euid)
+    }
+
+    pub fn sys_getgid(&mut self) -> SysResult {
+        info!("getgid");
+        Ok(self.process().gid)
+    }
+
+    pub fn sys_getegid(&mut self) -> SysResult {
+        info!("getegid");
+        Ok(self.process().egid)
+    }
+
+    /// Set the user id. Root sets the real, effective and saved ones, anyone
+    /// else may only switch the effective one to the real or the saved one
+    pub fn sys_setuid(&mut self, uid: usize) -> SysResult {
+        info!("setuid: {}", uid);
+        if uid as u32 == u32::MAX {
+            return Err(SysError::EINVAL);
+        }
+        let mut proc = self.process();
+        if proc.euid == 0 {
+            proc.uid = uid;
+            proc.euid = uid;
+            proc.suid = uid;
+        } else if uid == proc.uid || uid == proc.suid {
+            proc.euid = uid;
+        } else {
+            return Err(SysError::EPERM);
+        }
+        Ok(0)
+    }
+
+    /// Set the group id, with the same rules as `sys_setuid`
+    pub fn sys_setgid(&mut self, gid: usize) -> SysResult {
+        info!("setgid: {}", gid);
+        if gid as u32 == u32::MAX {
+            return Err(SysError::EINVAL);
+        }
+        let mut proc = self.process();
+        if proc.euid == 0 {
+            proc.gid = gid;
+            proc.egid = gid;
+            proc.sgid = gid;
+        } else if gid == proc.gid || gid == proc.sgid {
+            proc.egid = gid;
+        } else {
+            return Err(SysError::EPERM);
         }
+        Ok(0)
+    }
+
+    /// Set the real, effective and saved user ids, libc builds seteuid on it
+    pub fn sys_setresuid(&mut self, ruid: usize, euid: usize, suid: usize) -> SysResult {
+        info!(
+            "setresuid: {}, {}, {}",
+            ruid as i32, euid as i32, suid as i32
+        );
+        let mut proc = self.process();
+        let current = [proc.uid, proc.euid, proc.suid];
+        let [uid, euid, suid] = set_ids(current, [ruid, euid, suid], proc.euid == 0)?;
+        proc.uid = uid;
+        proc.euid = euid;
+        proc.suid = suid;
+        Ok(0)
+    }
+
+    /// Set the real, effective and saved group ids
+    pub fn sys_setresgid(&mut self, rgid: usize, egid: usize, sgid: usize) -> SysResult {
+        info!(
+            "setresgid: {}, {}, {}",
+            rgid as i32, egid as i32, sgid as i32
+        );
+        let mut proc = self.process();
+        let current = [proc.gid, proc.egid, proc.sgid];
+        let [gid, egid, sgid] = set_ids(current, [rgid, egid, sgid], proc.euid == 0)?;
+        proc.gid = gid;
+        proc.egid = egid;
+        proc.sgid = sgid;
+        Ok(0)
     }
 
     /// Exit the current thread
@@ -374,19 +521,35 @@ impl Syscall<'_> {
 
         proc.exit(exit_code);
         drop(proc);
-        // TODO: quit other threads
+        // other threads stop when they next come back from user mode
         self.exit = true;
         Ok(0)
     }
 
-    pub async fn sys_nanosleep(&mut self, req: UserInPtr<TimeSpec>) -> SysResult {
+    /// Sleep on the timer queue, which is checked on every timer tick,
+    /// so the actual sleep is rounded up to whole ticks
+    pub async fn sys_nanosleep(
+        &mut self,
+        req: UserInPtr<TimeSpec>,
+        mut rem: UserOutPtr<TimeSpec>,
+    ) -> SysResult {
         let time = req.read()?;
         info!("nanosleep: time: {:#?},", time);
+        if time.nsec >= 1_000_000_000 || (time.sec as isize) < 0 {
+            return Err(SysError::EINVAL);
+        }
         if !time.is_zero() {
-            self.sleep_for(time.to_duration()).await?;
-            if self.thread.has_signal_to_handle() {
+            let deadline = timer_now() + time.to_duration();
+            let ret = self.sleep_for(time.to_duration()).await;
+            if matches!(ret, Err(EINTR)) || self.thread.has_signal_to_handle() {
+                // tell the caller how much is left, to sleep again
+                if !rem.is_null() {
+                    let left = deadline.checked_sub(timer_now()).unwrap_or_default();
+                    rem.write(TimeSpec::from_duration(left))?;
+                }
                 return Err(EINTR);
             }
+            ret?;
         }
         Ok(0)
     }
@@ -451,6 +614,26 @@ impl Future for SleepFuture {
     }
 }
 
+/// Real, effective and saved ids after a setresuid or setresgid call.
+/// An id of -1 is kept, and without privilege each new id must be one of the current ones
+fn set_ids(
+    current: [usize; 3],
+    ids: [usize; 3],
+    privileged: bool,
+) -> Result<[usize; 3], SysError> {
+    let mut new = current;
+    for (new, &id) in new.iter_mut().zip(ids.iter()) {
+        if id as u32 == u32::MAX {
+            continue;
+        }
+        if !privileged && !current.contains(&id) {
+            return Err(SysError::EPERM);
+        }
+        *new = id;
+    }
+    Ok(new)
+}
+
 bitflags! {
     pub struct CloneFlags: usize {
         const CSIGNAL =         0x000000ff;
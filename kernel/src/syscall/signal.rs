@@ -19,8 +19,8 @@ impl Syscall<'_> {
                 signal, act, oldact, sigsetsize
             );
             use Signal::*;
-            if signal == SIGKILL
-                || signal == SIGSTOP
+            // the actions of SIGKILL and SIGSTOP can be queried, but never changed
+            if (!act.is_null() && (signal == SIGKILL || signal == SIGSTOP))
                 || sigsetsize != core::mem::size_of::<Sigset>()
             {
                 Err(EINVAL)
@@ -30,7 +30,9 @@ impl Syscall<'_> {
                     oldact.write(proc.dispositions[signum])?;
                 }
                 if !act.is_null() {
-                    let act = act.read()?;
+                    let mut act = act.read()?;
+                    act.mask.remove(SIGKILL);
+                    act.mask.remove(SIGSTOP);
                     info!("new action: {:?} -> {:x?}", signal, act);
                     proc.dispositions[signum] = act;
                 }
@@ -51,9 +53,12 @@ impl Syscall<'_> {
         let ptr: UserInPtr<SignalFrame> = UserInPtr::from(self.context.get_sp() - 8);
         let frame: SignalFrame = ptr.read()?;
 
-        // restore signal alternate stack
+        // restore signal alternate stack and the mask from before the handler
         let mut inner = self.thread.inner.lock();
         inner.signal_alternate_stack = frame.ucontext.stack;
+        inner.sig_mask = frame.ucontext.sig_mask;
+        inner.sig_mask.remove(Signal::SIGKILL);
+        inner.sig_mask.remove(Signal::SIGSTOP);
         drop(inner);
 
         // restore context
@@ -101,12 +106,25 @@ impl Syscall<'_> {
                 }
                 _ => return Err(EINVAL),
             }
+            // these two can't be blocked, silently
+            inner.sig_mask.remove(Signal::SIGKILL);
+            inner.sig_mask.remove(Signal::SIGSTOP);
         }
         return Ok(0);
     }
 
     /// sending signal sig to process pid
     pub fn sys_kill(&mut self, pid: isize, signum: usize) -> SysResult {
+        if signum == 0 {
+            // no signal, only check that the target exists
+            info!("kill: pid: {}, signal: 0", pid);
+            return match pid {
+                pid if pid > 0 => process(pid as usize).map(|_| 0).ok_or(ESRCH),
+                0 | -1 => Ok(0),
+                _ if process_group((-pid) as Pgid).is_empty() => Err(ESRCH),
+                _ => Ok(0),
+            };
+        }
         if let Some(signal) = <Signal as FromPrimitive>::from_usize(signum) {
             info!("kill: pid: {}, signal: {:?}", pid, signal);
             let info = Siginfo {
@@ -136,8 +154,12 @@ impl Syscall<'_> {
                     // TODO: check permissions
                     // sig is sent to every process for which the calling process
                     // has permission to send signals, except for process 1 (init)
-                    for process in PROCESSES.read().values() {
-                        send_signal(process.clone(), -1, info);
+                    // and the caller itself
+                    let self_pid = self.process().pid.get();
+                    for (&pid, process) in PROCESSES.read().iter() {
+                        if pid != Pid::INIT && pid != self_pid {
+                            send_signal(process.clone(), -1, info);
+                        }
                     }
                     Ok(0)
                 }
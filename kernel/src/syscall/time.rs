@@ -1,6 +1,7 @@
 //! Syscalls for time
 
 use super::*;
+use crate::arch::timer::timer_now;
 use crate::consts::USEC_PER_TICK;
 use core::time::Duration;
 use lazy_static::lazy_static;
@@ -26,7 +27,28 @@ impl Syscall<'_> {
     pub fn sys_clock_gettime(&mut self, clock: usize, mut ts: UserOutPtr<TimeSpec>) -> SysResult {
         info!("clock_gettime: clock: {:?}, ts: {:?}", clock, ts);
 
-        let timespec = TimeSpec::get_epoch();
+        const CLOCK_REALTIME: usize = 0;
+        const CLOCK_MONOTONIC: usize = 1;
+        const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+        const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+        const CLOCK_MONOTONIC_RAW: usize = 4;
+        const CLOCK_REALTIME_COARSE: usize = 5;
+        const CLOCK_MONOTONIC_COARSE: usize = 6;
+        const CLOCK_BOOTTIME: usize = 7;
+
+        let timespec = match clock {
+            // the same clock file timestamps are taken from
+            CLOCK_REALTIME | CLOCK_REALTIME_COARSE => TimeSpec::get_epoch(),
+            // time since boot, nothing is suspended here
+            CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => {
+                TimeSpec::from_duration(timer_now())
+            }
+            // TODO: no cpu time accounting yet, fall back to the time since boot
+            CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID => {
+                TimeSpec::from_duration(timer_now())
+            }
+            _ => return Err(SysError::EINVAL),
+        };
         ts.write(timespec)?;
         Ok(0)
     }
@@ -144,6 +166,13 @@ impl TimeSpec {
         Duration::new(self.sec as u64, self.nsec as u32)
     }
 
+    pub fn from_duration(duration: Duration) -> Self {
+        TimeSpec {
+            sec: duration.as_secs() as usize,
+            nsec: duration.subsec_nanos() as usize,
+        }
+    }
+
     pub fn get_epoch() -> Self {
         let usec = get_epoch_usec();
         TimeSpec {
@@ -152,7 +181,7 @@ impl TimeSpec {
         }
     }
 
-    // TODO: more precise; update when write
+    /// Set all timestamps to now, for a newly created inode
     pub fn update(inode: &Arc<dyn INode>) {
         let now = TimeSpec::get_epoch().into();
         if let Ok(mut metadata) = inode.metadata() {
@@ -164,6 +193,39 @@ impl TimeSpec {
         }
     }
 
+    /// Set mtime and ctime to now, after the content changed
+    pub fn update_modified(inode: &Arc<dyn INode>) {
+        let now = TimeSpec::get_epoch().into();
+        if let Ok(mut metadata) = inode.metadata() {
+            metadata.mtime = now;
+            metadata.ctime = now;
+            inode.set_metadata(&metadata).ok();
+        }
+    }
+
+    /// Set ctime to now, after only the metadata changed
+    pub fn update_changed(inode: &Arc<dyn INode>) {
+        if let Ok(mut metadata) = inode.metadata() {
+            metadata.ctime = TimeSpec::get_epoch().into();
+            inode.set_metadata(&metadata).ok();
+        }
+    }
+
+    /// Set atime to now after a read, like relatime:
+    /// only when it is not newer than mtime or ctime, to save metadata writes
+    pub fn update_accessed(inode: &Arc<dyn INode>) {
+        if let Ok(mut metadata) = inode.metadata() {
+            let atime = (metadata.atime.sec, metadata.atime.nsec);
+            if atime > (metadata.mtime.sec, metadata.mtime.nsec)
+                && atime > (metadata.ctime.sec, metadata.ctime.nsec)
+            {
+                return;
+            }
+            metadata.atime = TimeSpec::get_epoch().into();
+            inode.set_metadata(&metadata).ok();
+        }
+    }
+
     pub fn is_zero(&self) -> bool {
         self.sec == 0 && self.nsec == 0
     }
@@ -24,10 +24,14 @@ lazy_static! {
 
 pub fn timer() {
     let now = crate::arch::timer::timer_now();
+    // jitter between ticks is hard to predict
+    crate::random::add_entropy(now.as_nanos() as u64);
     NAIVE_TIMER.lock().expire(now);
 }
 
 pub fn serial(c: u8) {
+    let now = crate::arch::timer::timer_now().as_nanos() as u64;
+    crate::random::add_entropy(now ^ c as u64);
     if c == b'\r' {
         // in linux, we use '\n' instead
         crate::fs::TTY.push(b'\n');
@@ -0,0 +1 @@
+s() { while read k v; do case "$$k" in BlockCacheHits:) h=$$v;; BlockCacheMisses:) m=$$v;; esac; done </proc/meminfo; }; echo hello >/f; read l </f; s; h1=$$h; m1=$$m; read l </f; s; [ "$$m" = "$$m1" ] && [ "$$h" -gt "$$h1" ] && echo cached
@@ -0,0 +1 @@
+mkfifo /tmp/p; cat /tmp/p & echo hello >/tmp/p; wait
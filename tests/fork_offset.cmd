@@ -0,0 +1 @@
+exec 3>/tmp/f; printf 12345 >&3; (printf 67890 >&3); exec 3>&-; stat -c %s /tmp/f
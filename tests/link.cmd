@@ -0,0 +1 @@
+echo one >/tmp/a; ln /tmp/a /tmp/b; echo two >>/tmp/b; cat /tmp/a; stat -c %h /tmp/a; rm /tmp/a; cat /tmp/b; stat -c %h /tmp/b
@@ -0,0 +1 @@
+mkdir -p /mnt; touch /mnt/covered; mount -t ramfs none /mnt; echo mounted >/mnt/f; cat /mnt/f; ls /mnt; umount /mnt; ls /mnt
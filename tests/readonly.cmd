@@ -0,0 +1 @@
+echo data >/tmp/ro; chmod 444 /tmp/ro; chpst -u 1000:1000 dd if=/tmp/ro of=/tmp/ro conv=notrunc 2>&1; echo root >/tmp/ro; cat /tmp/ro
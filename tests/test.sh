@@ -1,12 +1,12 @@
 #!/bin/bash
-cd ../kernel && make sfsimg arch=riscv32 && cd ../tests
+cd ../kernel && make sfsimg ARCH=riscv32 && cd ../tests
 for f in *.cmd
 do
     echo testing $f begin
     (
         cd ../kernel
-        make build arch=riscv32 init=$(cat ../tests/$f)
-        exec timeout 10s make justruntest arch=riscv32 init=$(cat ../tests/$f)
+        make build ARCH=riscv32 INIT="$(cat ../tests/$f)"
+        exec timeout 10s make justtest ARCH=riscv32 INIT="$(cat ../tests/$f)"
     ) &
 
     pid=$!
@@ -0,0 +1 @@
+echo secret >/tmp/s; chmod 600 /tmp/s; chpst -u 1000:1000 cat /tmp/s 2>/dev/null || echo read denied; mkdir /tmp/d; echo hidden >/tmp/d/f; chmod 700 /tmp/d; chpst -u 1000:1000 cat /tmp/d/f 2>/dev/null || echo search denied; echo public >/tmp/pub; chmod 644 /tmp/pub; chpst -u 1000:1000 cat /tmp/pub
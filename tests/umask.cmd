@@ -0,0 +1 @@
+umask 077; touch /tmp/f; stat -c %a /tmp/f
@@ -0,0 +1 @@
+umask 077; mkdir /tmp/d; mkfifo /tmp/p; stat -c '%a %F' /tmp/d /tmp/p